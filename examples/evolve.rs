@@ -5,24 +5,64 @@ extern crate rand;
 extern crate rustc_serialize;
 
 pub use rand::Rng;
+use std::path::Path;
 
 use ast::structure::{Program, Number};
 use ast::depth::Depth;
 use ast::simulation;
+use ast::simulation::{Limits, Outcome};
 use ast::serialize;
 use ast::simplify::Simplify;
 use ast::data::SensorData;
+use ast::random::Generator;
 use ast::darwin::evolve;
+use ast::darwin::selection::Lexicase;
+use ast::darwin::optimise::Optimiser;
+use ast::darwin::crossover::Crossover;
+use ast::darwin::stats::StatsLog;
+use ast::darwin::termination::Termination;
 use ast::num::{square, partial_max, TAU};
-use ast::darwin::evolve::{ScoreCard,OptimumKeeper};
+use ast::darwin::evolve::{Config,Population,ScoreCard,OptimumKeeper,HallOfFame};
 
 const POPULATION_SIZE : usize = 2000;
 const TRIALS_PER_PROGRAM : u32 = 10;
-const TOURNAMENT_SIZE : usize = 100;
+const THREADS : usize = 8;
+const MIN_DEPTH : u32 = 2;
+const MAX_DEPTH : u32 = 6;
+/// Offspring deeper than this are rejected
+const MAX_PROGRAM_DEPTH : u32 = 17;
 
 const REPRODUCE_WEIGHT : u32 = 10;
 const MUTATE_WEIGHT : u32 = 10;
 const CROSSOVER_WEIGHT : u32 = 10;
+const ELITISM : usize = 2;
+const HALL_OF_FAME_SIZE : usize = 10;
+
+/// Number of best programs of which the constants are tuned every generation
+const TUNED_PROGRAMS : usize = 10;
+const TUNING_ITERATIONS : u32 = 20;
+
+/// The run stops at whichever of these comes first
+const MAX_GENERATIONS : u32 = 1000;
+const STAGNATION_LIMIT : u32 = 100;
+/// Landing in every scenario
+const LANDING_STREAK : u32 = TRIALS_PER_PROGRAM;
+
+/// Written after every scoring round, `evolve --resume` continues from it
+const CHECKPOINT_FILE : &'static str = "evolve-checkpoint.json";
+
+/// The best distinct programs so far, written after every scoring round
+const HALL_OF_FAME_FILE : &'static str = "evolve-hall-of-fame.json";
+
+/// Statistics of every generation, as CSV (or NDJSON for a .json extension)
+const STATS_FILE : &'static str = "evolve-stats.csv";
+
+/// Seed of the run, taken from the first argument or picked at random
+fn seed() -> usize {
+    std::env::args().nth(1)
+        .map(|arg| arg.parse().expect("Usage: SEED must be a number"))
+        .unwrap_or_else(rand::random)
+}
 
 fn random_start_position<R: rand::Rng>(rng: &mut R) -> SensorData {
     let k = if rng.gen::<bool>() { 1.0 } else { -1.0 };
@@ -35,45 +75,43 @@ fn random_start_position<R: rand::Rng>(rng: &mut R) -> SensorData {
 //        .with_o(rng.next_f32() * TAU)
 }
 
-/// Score a program by scoring a single run
+/// Score a program by scoring a single run from the given start position
 ///
 /// Ultimate score is composed of:
 /// - How many frames we survived (higher is better)
 /// - What our maximum height was (lower is better)
 /// - If we landed (if so then FUCK YEAH)
-fn score_single_run<R: rand::Rng>(program: &Program, rng: &mut R) -> ScoreCard {
-    let mut sensor_data = random_start_position(rng);
+fn score_single_run(program: &Program, start: SensorData) -> ScoreCard {
     let world = simulation::World::new().with_max_landing_speed(0.5);
-    let mut trace = serialize::GameTrace::new();
+    let (trace, outcome) = simulation::run_episode(program, start, &world, &Limits::new());
 
-    let mut total_height: Number = 0.;
-    let mut total_fuel: Number = 0.;
-
-    trace.add(&sensor_data);
-    while !sensor_data.hit_ground {
-        total_height += square(sensor_data.y);
-        total_fuel += square(sensor_data.fuel);
-        
-
-        simulation::next_program(&mut sensor_data, &program, &world);
-        trace.add(&sensor_data);
-    };
+    // The states that a frame started from, which leaves out the last one
+    let started = &trace.trace()[..trace.trace().len() - 1];
+    let total_height: Number = started.iter().map(|s| square(s.y)).sum();
+    let total_fuel: Number = started.iter().map(|s| square(s.fuel)).sum();
+    let last = *trace.trace().last().unwrap();
 
     let frames = trace.frames() as Number;
     ScoreCard::new(vec![
         ("survival_bonus",   3.0 * frames),
         ("height_penalty",   -(0.01 * total_height / frames)),
         ("fuel_bonus",        (100.0 * total_fuel / frames)),
-        ("hit_ground_bonus", if sensor_data.hit_ground { 10.0 } else { 0.0 }),
-        ("crash_penalty",    sensor_data.crash_speed),
-        ("angle_penalty",    square(sensor_data.o) * -100.0),
-        ("success_bonus",    if sensor_data.landed { 10000.0 } else { 0.0 }),
+        ("hit_ground_bonus", if last.hit_ground { 10.0 } else { 0.0 }),
+        ("crash_penalty",    last.crash_speed),
+        ("angle_penalty",    square(last.o) * -100.0),
+        ("success_bonus",    if outcome == Outcome::Landed { 10000.0 } else { 0.0 }),
     ], trace)
 }
 
-/// Score a program by averaging the score of multiple random runs
-fn score_program<R: rand::Rng>(program: &Program, rng: &mut R) -> ScoreCard {
-    let best_run = partial_max((0..TRIALS_PER_PROGRAM).map(|_| score_single_run(program, rng))).unwrap();
+/// Score a program by its best run out of a number of scenarios
+///
+/// The total score of every run is kept as a test case for lexicase selection, and whether it
+/// landed for the landing streak.
+fn score_program(program: &Program, scenarios: &[SensorData]) -> ScoreCard {
+    let runs = scenarios.iter().map(|&start| score_single_run(program, start)).collect::<Vec<ScoreCard>>();
+    let cases = runs.iter().map(|run| run.total_score()).collect();
+    let landings = runs.iter().map(|run| run.trace().trace().last().map_or(false, |s| s.landed)).collect();
+    let best_run = partial_max(runs.into_iter()).unwrap().with_cases(cases).with_landings(landings);
 
     // Give a penalty for program depth. Since this is the same for all
     // runs, we only do it here (for mucho saved speed!)
@@ -83,36 +121,81 @@ fn score_program<R: rand::Rng>(program: &Program, rng: &mut R) -> ScoreCard {
 }
 
 fn main() {
-    // Generate initial random population
-    println_err!("Generating initial population");
-    let mut population = evolve::random_population::<Program>(POPULATION_SIZE);
-    let mut rng = rand::StdRng::new().unwrap();
+    let checkpoint = Path::new(CHECKPOINT_FILE);
+    let resume = std::env::args().nth(1) == Some("--resume".to_string());
+    let (population, config, mut keeper) = if resume {
+        println_err!("Resuming from {}", CHECKPOINT_FILE);
+        Population::<Program>::load_checkpoint(checkpoint).expect("Error loading checkpoint")
+    } else {
+        let config = Config::new()
+            .with_reproduce_weight(REPRODUCE_WEIGHT)
+            .with_mutate_weight(MUTATE_WEIGHT)
+            .with_crossover_weight(CROSSOVER_WEIGHT)
+            .with_crossover(Crossover::SizeFair)
+            .with_max_depth(MAX_PROGRAM_DEPTH)
+            .with_elitism(ELITISM)
+            .with_seed(seed());
+
+        // Generate initial random population
+        println_err!("Generating initial population");
+        let generator = Generator::new().with_min_depth(MIN_DEPTH).with_max_depth(MAX_DEPTH);
+        let population = evolve::generated_population::<Program, _>(POPULATION_SIZE, &generator, &mut config.rng(0));
+        (population, config, OptimumKeeper::<Program>::new())
+    };
+    println_err!("Seed: {}", config.seed);
+    let hall_of_fame_file = Path::new(HALL_OF_FAME_FILE);
+    // The scores of an earlier run only compare to this one when it is continued
+    let mut hall_of_fame = if resume {
+        HallOfFame::<Program>::load(hall_of_fame_file).unwrap_or_else(|_| HallOfFame::new(HALL_OF_FAME_SIZE))
+    } else {
+        HallOfFame::new(HALL_OF_FAME_SIZE)
+    };
+    let selection = Lexicase::new();
+    let optimiser = Optimiser::new().with_iterations(TUNING_ITERATIONS);
     let mut stdout = std::io::stdout();
-    let mut keeper = OptimumKeeper::<Program>::new();
-
-    loop {
-        println_err!("[{}] Scoring", population.generation);
-        population.score(|p| score_program(p, &mut rng));
-        {
-            let winner = population.winner();
-            println_err!("[{}] Best score: {}", population.generation, winner.score.total_score());
-            
-            if keeper.improved(&winner.program, &winner.score, population.generation) {
-                let random_score = score_single_run(&winner.program, &mut rng);
-
-                let _ = serialize::writeln(&serialize::TraceOutput {
-                    generation: population.generation,
-                    program: &winner.program.simplify(),
-                    score_card: &random_score
-                }, &mut stdout);
-            }
+    // A resumed run continues the statistics of the run it picks up
+    let mut stats_log = if resume {
+        StatsLog::append(Path::new(STATS_FILE))
+    } else {
+        StatsLog::create(Path::new(STATS_FILE))
+    }.expect("Error opening statistics file");
+
+    let termination = Termination::new()
+        .with_max_generations(MAX_GENERATIONS)
+        .with_stagnation(STAGNATION_LIMIT)
+        .with_landing_streak(LANDING_STREAK);
+
+    let (population, reason) = population.run_until(&config, &selection, &termination, |population, rng| {
+        // All programs of a generation face the same scenarios, so they can be compared per case
+        let scenarios = (0..TRIALS_PER_PROGRAM).map(|_| random_start_position(rng)).collect::<Vec<SensorData>>();
+
+        // A resumed population may already have been scored
+        if population.scores.len() != population.n() {
+            println_err!("[{}] Scoring", population.generation);
+            population.score_parallel(THREADS, config.seed, |p, _| score_program(p, &scenarios));
+            population.save_checkpoint(&config, &keeper, checkpoint).expect("Error saving checkpoint");
         }
+        // Lamarckian step: tuned constants are passed on to the next generation
+        optimiser.improve_population(population, TUNED_PROGRAMS, |p| score_program(p, &scenarios), rng);
+        if hall_of_fame.update(population) {
+            hall_of_fame.save(hall_of_fame_file).expect("Error saving hall of fame");
+        }
+        let stats = population.stats();
+        println_err!("{}", stats);
+        stats_log.write(&stats).expect("Error writing statistics");
+
+        let winner = population.winner();
+        println_err!("[{}] Best score: {}", population.generation, winner.score.total_score());
 
-        println_err!("[{}] Evolving", population.generation);
-        population = population.evolve(TOURNAMENT_SIZE,
-                                       REPRODUCE_WEIGHT, 
-                                       MUTATE_WEIGHT, 
-                                       CROSSOVER_WEIGHT,
-                                       &mut rng);
-    }
+        if keeper.improved(&winner.program, &winner.score, population.generation) {
+            let random_score = score_single_run(&winner.program, random_start_position(rng));
+
+            let _ = serialize::writeln(&serialize::TraceOutput {
+                generation: population.generation,
+                program: &winner.program.simplify(),
+                score_card: &random_score
+            }, &mut stdout);
+        }
+    });
+    println_err!("[{}] Stopped: {}", population.generation, reason);
 }
@@ -0,0 +1,90 @@
+//! Multi-objective evolution example
+//!
+//! Trades off landing, fuel left and program size with NSGA-II, and writes the Pareto front
+//! after every generation.
+#[macro_use]
+extern crate ast;
+extern crate rand;
+extern crate rustc_serialize;
+
+pub use rand::Rng;
+use std::path::Path;
+
+use ast::structure::{Program, Number};
+use ast::size::Size;
+use ast::simulation;
+use ast::simulation::{Limits, Outcome};
+use ast::data::SensorData;
+use ast::random::Generator;
+use ast::darwin::evolve;
+use ast::darwin::nsga2::Nsga2;
+use ast::darwin::cache::FitnessCache;
+use ast::darwin::evolve::{Config,ScoreCard};
+
+const POPULATION_SIZE : usize = 500;
+const TRIALS_PER_PROGRAM : u32 = 10;
+const THREADS : usize = 8;
+const CACHE_SIZE : usize = 5000;
+
+/// Written after every generation
+const PARETO_FRONT_FILE : &'static str = "pareto-front.json";
+
+/// Seed of the run, taken from the first argument or picked at random
+fn seed() -> usize {
+    std::env::args().nth(1)
+        .map(|arg| arg.parse().expect("Usage: SEED must be a number"))
+        .unwrap_or_else(rand::random)
+}
+
+fn random_start_position<R: rand::Rng>(rng: &mut R) -> SensorData {
+    SensorData::new()
+        .with_x(0.0)
+        .with_y(rng.next_f32() * 400.0 + 50.0)
+        .with_o(0.0)
+}
+
+/// Score a program on the fraction of landings, the average fuel left and its size
+fn score_program(program: &Program, scenarios: &[SensorData]) -> ScoreCard {
+    let world = simulation::World::new().with_max_landing_speed(0.5);
+    let runs = scenarios.iter()
+        .map(|&start| simulation::run_episode(program, start, &world, &Limits::new()))
+        .collect::<Vec<_>>();
+
+    let landed = runs.iter().filter(|&&(_, outcome)| outcome == Outcome::Landed).count() as Number;
+    let fuel: Number = runs.iter().map(|&(ref trace, _)| trace.trace().last().unwrap().fuel).sum();
+
+    let n = scenarios.len() as Number;
+    ScoreCard::new(vec![
+        ("landed", landed / n),
+        ("fuel",   fuel / n),
+        ("size",   -(program.size() as Number))
+    ], runs.into_iter().last().unwrap().0)
+}
+
+fn main() {
+    let config = Config::new().with_seed(seed());
+    let nsga = Nsga2::new(vec!["landed", "fuel", "size"]);
+    println_err!("Seed: {}", config.seed);
+
+    // The scenarios stay the same during the run, so parents and offspring can be compared
+    let mut rng = config.rng(0);
+    let generator = Generator::new();
+    let mut population = evolve::generated_population::<Program, _>(POPULATION_SIZE, &generator, &mut rng);
+    let scenarios = (0..TRIALS_PER_PROGRAM).map(|_| random_start_position(&mut rng)).collect::<Vec<SensorData>>();
+    // Scoring is deterministic, so programs that were scored before can be looked up
+    let mut cache = FitnessCache::new(CACHE_SIZE);
+    population.score_parallel_cached(THREADS, &mut cache, |p| score_program(p, &scenarios));
+
+    loop {
+        let front = nsga.pareto_front(&population);
+        println_err!("[{}] Pareto front of {} programs", population.generation, front.members.len());
+        front.save(Path::new(PARETO_FRONT_FILE)).expect("Error saving Pareto front");
+
+        let mut rng = config.rng(population.generation + 1);
+        let mut offspring = population.evolve(&config, &nsga, &mut rng);
+        offspring.score_parallel_cached(THREADS, &mut cache, |p| score_program(p, &scenarios));
+        println_err!("{}", offspring.stats().with_cache(&cache));
+        cache.reset_counts();
+        population = nsga.survivors(population, offspring);
+    }
+}
@@ -11,24 +11,36 @@ use std::iter::Iterator;
 
 use ast::structure::{Condition, Number};
 use ast::simulation;
+use ast::simulation::{Limits, Outcome};
 use ast::serialize;
 use ast::depth::Depth;
 use ast::simplify::Simplify;
 use ast::data::SensorData;
 use ast::num::{square, partial_max, partial_min};
 use ast::darwin::evolve;
-use ast::darwin::evolve::{ScoreCard,OptimumKeeper};
+use ast::darwin::selection::Tournament;
+use ast::darwin::termination::Termination;
+use ast::darwin::evolve::{Config,ScoreCard,OptimumKeeper};
 use ast::serialize::GameTrace;
 
 
 const POPULATION_SIZE : usize = 2000;
 const TRIALS_PER_PROGRAM : usize = 10;
 const TOURNAMENT_SIZE : usize = 100;
+const STAGNATION_LIMIT : u32 = 50;
+const LANDING_STREAK : u32 = 10;
 
 const REPRODUCE_WEIGHT : u32 = 10;
 const MUTATE_WEIGHT : u32 = 10;
 const CROSSOVER_WEIGHT : u32 = 10;
 
+/// Seed of the run, taken from the first argument or picked at random
+fn seed() -> usize {
+    std::env::args().nth(1)
+        .map(|arg| arg.parse().expect("Usage: SEED must be a number"))
+        .unwrap_or_else(rand::random)
+}
+
 fn random_start_position<R: rand::Rng>(rng: &mut R) -> SensorData {
     SensorData::new()
         .with_x(0.0)
@@ -43,30 +55,23 @@ fn random_start_position<R: rand::Rng>(rng: &mut R) -> SensorData {
 /// - What our maximum height was (lower is better)
 /// - If we landed (if so then FUCK YEAH)
 fn score_single_run<R: rand::Rng>(program: &Condition, rng: &mut R) -> ScoreCard {
-    let mut sensor_data = random_start_position(rng);
     let world = simulation::World::new().with_max_landing_speed(0.5);
-    let mut trace = serialize::GameTrace::new();
-
-    let mut total_height: Number = 0.;
-    let mut total_fuel: Number = 0.;
+    let (trace, outcome) = simulation::run_episode(program, random_start_position(rng), &world, &Limits::new());
 
-    trace.add(&sensor_data);
-    while !sensor_data.hit_ground {
-        total_height += square(sensor_data.y);
-        total_fuel += square(sensor_data.fuel);
-
-        simulation::next_condition(&mut sensor_data, &program, &world);
-        trace.add(&sensor_data);
-    };
+    // The states that a frame started from, which leaves out the last one
+    let started = &trace.trace()[..trace.trace().len() - 1];
+    let total_height: Number = started.iter().map(|s| square(s.y)).sum();
+    let total_fuel: Number = started.iter().map(|s| square(s.fuel)).sum();
+    let last = *trace.trace().last().unwrap();
 
     let frames = trace.frames() as Number;
     ScoreCard::new(vec![
         ("survival_bonus",   3.0 * frames),
         ("height_penalty",   -(0.01 * total_height / frames)),
         ("fuel_bonus",        (100.0 * total_fuel / frames)),
-        ("hit_ground_bonus", if sensor_data.hit_ground { 10.0 } else { 0.0 }),
-        ("crash_penalty",    sensor_data.crash_speed),
-        ("success_bonus",    if sensor_data.landed { 10000.0 } else { 0.0 }),
+        ("hit_ground_bonus", if last.hit_ground { 10.0 } else { 0.0 }),
+        ("crash_penalty",    last.crash_speed),
+        ("success_bonus",    if outcome == Outcome::Landed { 10000.0 } else { 0.0 }),
         ("complexity_pentalty", program.depth() as f32 * -5.0)
     ], trace)
 }
@@ -75,16 +80,18 @@ fn scorecards_avg<XS: Iterator<Item=ScoreCard>>(xs: XS) -> ScoreCard {
     let mut total = 0.0;
     let mut count = 0.0;
     let mut last_trace = GameTrace::new();
+    let mut landings = Vec::new();
     for x in xs {
         total += x.total_score();
         count += 1.0;
+        landings.push(x.trace().trace().last().map_or(false, |s| s.landed));
         last_trace = x.into_trace();
     }
 
     // Just create a fake trace with this avg score, we'll create a new trace
     // later to display.
     let scores = vec![("fake_avg", total)];
-    ScoreCard::new(scores, last_trace)
+    ScoreCard::new(scores, last_trace).with_landings(landings)
 }
 
 /// Score a program by averaging the score of multiple random runs
@@ -95,37 +102,41 @@ fn score_program<R: rand::Rng>(program: &Condition, rng: &mut R) -> ScoreCard {
 fn main() {
     env_logger::init().expect("Error initializing logger");
 
+    let config = Config::new()
+        .with_reproduce_weight(REPRODUCE_WEIGHT)
+        .with_mutate_weight(MUTATE_WEIGHT)
+        .with_crossover_weight(CROSSOVER_WEIGHT)
+        .with_seed(seed());
+    println_err!("Seed: {}", config.seed);
+
     // Generate initial random population
     println_err!("Generating initial population");
-    let mut population = evolve::random_population::<Condition>(POPULATION_SIZE);
-    let mut rng = rand::StdRng::new().unwrap();
+    let population = evolve::random_population::<Condition, _>(POPULATION_SIZE, &mut config.rng(0));
+    let selection = Tournament::new(TOURNAMENT_SIZE);
     let mut stdout = std::io::stdout();
     let mut keeper = OptimumKeeper::<Condition>::new();
 
-    loop {
+    let termination = Termination::new()
+        .with_stagnation(STAGNATION_LIMIT)
+        .with_landing_streak(LANDING_STREAK);
+
+    let (population, reason) = population.run_until(&config, &selection, &termination, |population, rng| {
         serialize::log(&population.population);
         println_err!("[{}] Scoring", population.generation);
-        population.score(|p| score_program(p, &mut rng));
-        {
-            let winner = population.winner();
-            println_err!("[{}] Best score: {}", population.generation, winner.score.total_score());
-            
-            if keeper.improved(&winner.program, &winner.score, population.generation) {
-                let random_score = score_single_run(&winner.program, &mut rng);
-
-                let _ = serialize::writeln(&serialize::TraceOutput {
-                    generation: population.generation,
-                    program: &winner.program.simplify(),
-                    score_card: &random_score,
-                }, &mut stdout);
-            }
-        }
+        population.score(|p| score_program(p, rng));
 
-        println_err!("[{}] Evolving", population.generation);
-        population = population.evolve(TOURNAMENT_SIZE,
-                                       REPRODUCE_WEIGHT, 
-                                       MUTATE_WEIGHT, 
-                                       CROSSOVER_WEIGHT,
-                                       &mut rng);
-    }
+        let winner = population.winner();
+        println_err!("[{}] Best score: {}", population.generation, winner.score.total_score());
+
+        if keeper.improved(&winner.program, &winner.score, population.generation) {
+            let random_score = score_single_run(&winner.program, rng);
+
+            let _ = serialize::writeln(&serialize::TraceOutput {
+                generation: population.generation,
+                program: &winner.program.simplify(),
+                score_card: &random_score,
+            }, &mut stdout);
+        }
+    });
+    println_err!("[{}] Stopped: {}", population.generation, reason);
 }
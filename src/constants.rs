@@ -0,0 +1,137 @@
+//! Read and replace the constants of an expression tree
+//!
+//! Constants are listed in the order in which they appear in the source of a tree, which is also
+//! the order in which `with_constants` puts them back.
+
+use super::structure::{Program,Condition,Expression,Number};
+
+pub trait Constants: Sized {
+    /// Append all constants of the tree to `into`
+    fn collect_constants(&self, into: &mut Vec<Number>);
+
+    /// Copy the tree, taking its constants from `constants`
+    ///
+    /// Constants for which the iterator runs out keep their value.
+    fn replace_constants<I: Iterator<Item=Number>>(&self, constants: &mut I) -> Self;
+
+    fn constants(&self) -> Vec<Number> {
+        let mut constants = Vec::new();
+        self.collect_constants(&mut constants);
+        constants
+    }
+
+    fn with_constants(&self, constants: &[Number]) -> Self {
+        self.replace_constants(&mut constants.iter().cloned())
+    }
+}
+
+impl Constants for Program {
+    fn collect_constants(&self, into: &mut Vec<Number>) {
+		match *self {
+			Program::If(ref condition, ref left, ref right) => {
+                condition.collect_constants(into);
+                left.collect_constants(into);
+                right.collect_constants(into);
+            },
+			Program::Command(_) => {}
+		}
+    }
+
+    fn replace_constants<I: Iterator<Item=Number>>(&self, constants: &mut I) -> Self {
+		match *self {
+			Program::If(ref condition, ref left, ref right) => {
+                let condition = condition.replace_constants(constants);
+                let left = left.replace_constants(constants);
+                let right = right.replace_constants(constants);
+                Program::If(Box::new(condition), Box::new(left), Box::new(right))
+            },
+			Program::Command(_) => self.clone()
+		}
+    }
+}
+
+impl Constants for Condition {
+    fn collect_constants(&self, into: &mut Vec<Number>) {
+		match *self {
+			Condition::Not(ref condition)                => condition.collect_constants(into),
+			Condition::Or(ref left, ref right)           => { left.collect_constants(into); right.collect_constants(into); },
+			Condition::And(ref left, ref right)          => { left.collect_constants(into); right.collect_constants(into); },
+			Condition::Less(ref left, ref right)         => { left.collect_constants(into); right.collect_constants(into); },
+			Condition::LessEqual(ref left, ref right)    => { left.collect_constants(into); right.collect_constants(into); },
+			Condition::Equal(ref left, ref right)        => { left.collect_constants(into); right.collect_constants(into); },
+			Condition::GreaterEqual(ref left, ref right) => { left.collect_constants(into); right.collect_constants(into); },
+			Condition::Greater(ref left, ref right)      => { left.collect_constants(into); right.collect_constants(into); },
+            _ => {}
+		}
+    }
+
+    fn replace_constants<I: Iterator<Item=Number>>(&self, constants: &mut I) -> Self {
+        macro_rules! both {
+            ($variant:path, $left:expr, $right:expr) => {{
+                let left = $left.replace_constants(constants);
+                let right = $right.replace_constants(constants);
+                $variant(Box::new(left), Box::new(right))
+            }}
+        }
+
+		match *self {
+			Condition::Not(ref condition)                => Condition::Not(Box::new(condition.replace_constants(constants))),
+			Condition::Or(ref left, ref right)           => both!(Condition::Or, left, right),
+			Condition::And(ref left, ref right)          => both!(Condition::And, left, right),
+			Condition::Less(ref left, ref right)         => both!(Condition::Less, left, right),
+			Condition::LessEqual(ref left, ref right)    => both!(Condition::LessEqual, left, right),
+			Condition::Equal(ref left, ref right)        => both!(Condition::Equal, left, right),
+			Condition::GreaterEqual(ref left, ref right) => both!(Condition::GreaterEqual, left, right),
+			Condition::Greater(ref left, ref right)      => both!(Condition::Greater, left, right),
+            _ => self.clone()
+		}
+    }
+}
+
+impl Constants for Expression {
+    fn collect_constants(&self, into: &mut Vec<Number>) {
+		match *self {
+			Expression::Constant(value)               => into.push(value),
+			Expression::Plus(ref left, ref right)     => { left.collect_constants(into); right.collect_constants(into); },
+			Expression::Minus(ref left, ref right)    => { left.collect_constants(into); right.collect_constants(into); },
+			Expression::Multiply(ref left, ref right) => { left.collect_constants(into); right.collect_constants(into); },
+			Expression::Divide(ref left, ref right)   => { left.collect_constants(into); right.collect_constants(into); },
+            _ => {}
+		}
+    }
+
+    fn replace_constants<I: Iterator<Item=Number>>(&self, constants: &mut I) -> Self {
+        macro_rules! both {
+            ($variant:path, $left:expr, $right:expr) => {{
+                let left = $left.replace_constants(constants);
+                let right = $right.replace_constants(constants);
+                $variant(Box::new(left), Box::new(right))
+            }}
+        }
+
+		match *self {
+			Expression::Constant(value)               => Expression::Constant(constants.next().unwrap_or(value)),
+			Expression::Plus(ref left, ref right)     => both!(Expression::Plus, left, right),
+			Expression::Minus(ref left, ref right)    => both!(Expression::Minus, left, right),
+			Expression::Multiply(ref left, ref right) => both!(Expression::Multiply, left, right),
+			Expression::Divide(ref left, ref right)   => both!(Expression::Divide, left, right),
+            _ => self.clone()
+		}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::*; }
+
+    use super::*;
+
+    #[test]
+    fn constants_are_replaced_in_source_order() {
+        let program = iff!(less!(constant!(1.0), plus!(y!(), constant!(2.0))), skip!(), iff!(greater!(vy!(), constant!(3.0)), left!(), right!()));
+
+        assert_eq!(vec![1.0, 2.0, 3.0], program.constants());
+        assert_eq!(vec![4.0, 5.0, 3.0], program.with_constants(&[4.0, 5.0]).constants());
+    }
+}
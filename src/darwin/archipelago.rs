@@ -0,0 +1,286 @@
+//! Island-model evolution
+//!
+//! An `Archipelago` evolves several populations side by side. Every so many generations the best
+//! programs of an island migrate to its neighbours, where they replace the worst programs. The
+//! islands explore different parts of the search space in between, which keeps a run from
+//! converging on a single solution too early.
+use std::cmp::max;
+use std::fmt;
+use std::thread;
+use super::super::copy::Copyable;
+use super::super::depth::Depth;
+use super::super::size::Size;
+use super::super::source::Source;
+use super::super::structure::Number;
+use super::super::visit::Visitable;
+use super::evolve::{Config, Population, ScoreCard, CreatureScore};
+use super::selection::Selection;
+use rand;
+use rand::{Rand, Rng, SeedableRng, StdRng};
+
+/// Which islands send migrants to which
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub enum Topology {
+    /// Every island sends to the next one, the last to the first
+    Ring,
+    /// Every island sends to all other islands
+    FullyConnected,
+    /// Every island sends to one other island, picked anew at every migration
+    Random
+}
+
+/// When and how programs move between islands
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Migration {
+    pub topology: Topology,
+    /// Number of generations between migrations
+    pub interval: u32,
+    /// Number of best programs an island sends to each of its neighbours
+    pub migrants: usize
+}
+
+impl Migration {
+    pub fn new() -> Migration {
+        Migration { topology: Topology::Ring, interval: 10, migrants: 5 }
+    }
+
+    pub fn with_topology(self, topology: Topology) -> Migration {
+        Migration { topology: topology, ..self }
+    }
+
+    pub fn with_interval(self, interval: u32) -> Migration {
+        Migration { interval: interval, ..self }
+    }
+
+    pub fn with_migrants(self, migrants: usize) -> Migration {
+        Migration { migrants: migrants, ..self }
+    }
+
+    /// Whether migration takes place after the given generation has been scored
+    pub fn is_due(&self, generation: u32) -> bool {
+        self.interval > 0 && generation > 0 && generation % self.interval == 0
+    }
+
+    /// The islands that island i sends migrants to
+    fn destinations<R: rand::Rng>(&self, i: usize, islands: usize, rng: &mut R) -> Vec<usize> {
+        if islands < 2 {
+            return Vec::new();
+        }
+        match self.topology {
+            Topology::Ring           => vec![(i + 1) % islands],
+            Topology::FullyConnected => (0..islands).filter(|&j| j != i).collect(),
+            Topology::Random         => vec![(i + rng.gen_range(1, islands)) % islands]
+        }
+    }
+}
+
+/// A number of populations that evolve separately, apart from migration
+pub struct Archipelago<P: Rand+Clone+Source> {
+    pub islands: Vec<Population<P>>,
+    pub migration: Migration,
+    /// Number of islands that are scored and evolved at the same time
+    pub threads: usize
+}
+
+impl <P: Rand+Clone+Source> Archipelago<P> {
+    /// Create an archipelago that works on one island at a time
+    pub fn new(islands: Vec<Population<P>>) -> Archipelago<P> {
+        Archipelago { islands: islands, migration: Migration::new(), threads: 1 }
+    }
+
+    pub fn with_migration(self, migration: Migration) -> Archipelago<P> {
+        Archipelago { migration: migration, ..self }
+    }
+
+    pub fn with_threads(self, threads: usize) -> Archipelago<P> {
+        Archipelago { threads: threads, ..self }
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.islands.first().map_or(0, |island| island.generation)
+    }
+
+    /// Apply a scoring function to all islands
+    ///
+    /// Like `Population::score_parallel`, every program gets its own random number generator,
+    /// so the scores do not depend on the number of threads.
+    pub fn score<F>(&mut self, seed: usize, scoring_fn: F)
+        where F: Fn(&P, &mut StdRng) -> ScoreCard + Sync,
+              P: Send+Sync
+    {
+        let chunk = self.chunk_size();
+        let scoring_fn = &scoring_fn;
+        thread::scope(|scope| {
+            for (c, islands) in self.islands.chunks_mut(chunk).enumerate() {
+                scope.spawn(move || {
+                    for (i, island) in islands.iter_mut().enumerate() {
+                        island.score_parallel(1, island_seed(seed, c * chunk + i), scoring_fn);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Send copies of the best programs of every island to its neighbours
+    ///
+    /// The islands must be scored. Migrants keep their scores and replace the worst programs of
+    /// the island they arrive at.
+    pub fn migrate<R: rand::Rng>(&mut self, rng: &mut R) {
+        let count = self.islands.len();
+        let mut arrivals: Vec<Vec<(P, ScoreCard)>> = vec![Vec::new(); count];
+        for i in 0..count {
+            let island = &self.islands[i];
+            let emigrants = island.best_i(self.migration.migrants);
+            for j in self.migration.destinations(i, count, rng) {
+                arrivals[j].extend(emigrants.iter().map(|&k| (island.population[k].clone(), island.scores[k].clone())));
+            }
+        }
+
+        for (island, arrivals) in self.islands.iter_mut().zip(arrivals.into_iter()) {
+            let mut worst = island.best_i(island.n());
+            worst.reverse();
+            for (k, (program, score)) in worst.into_iter().zip(arrivals.into_iter()) {
+                island.population[k] = program;
+                island.scores[k] = score;
+            }
+        }
+    }
+
+    /// Produce the next generation of every island, after migrating if it is due
+    ///
+    /// Island i evolves with `config` under a seed derived from `config.seed` and i, while
+    /// migration draws from `config.rng(generation + 1)`.
+    pub fn evolve<'a, S>(&'a mut self, config: &Config, selection: &S) -> Archipelago<P>
+        where P: Visitable<'a>+Copyable+Depth+Size+Send+Sync,
+              S: Selection<P>+Sync
+    {
+        let generation = self.generation();
+        if self.migration.is_due(generation) {
+            debug!("Migrating after generation {}", generation);
+            self.migrate(&mut config.rng(generation + 1));
+        }
+
+        let this: &'a Archipelago<P> = self;
+        let chunk = this.chunk_size();
+        let islands = thread::scope(|scope| {
+            let workers = this.islands.chunks(chunk).enumerate().map(|(c, islands)| scope.spawn(move || {
+                islands.iter().enumerate().map(|(i, island)| {
+                    let island_config = config.with_seed(island_seed(config.seed, c * chunk + i));
+                    island.evolve(&island_config, selection, &mut island_config.rng(generation + 1))
+                }).collect::<Vec<Population<P>>>()
+            })).collect::<Vec<_>>();
+
+            workers.into_iter()
+                .flat_map(|worker| worker.join().expect("evolution thread panicked"))
+                .collect::<Vec<Population<P>>>()
+        });
+
+        Archipelago { islands: islands, migration: this.migration, threads: this.threads }
+    }
+
+    /// Statistics of every scored island
+    pub fn stats(&self) -> Vec<IslandStats> {
+        self.islands.iter().enumerate().map(|(i, island)| IslandStats::new(i, island)).collect()
+    }
+
+    /// Return the best program of all islands
+    pub fn winner(&self) -> CreatureScore<P> {
+        let mut winners = self.islands.iter().map(|island| island.winner());
+        let first = winners.next().expect("archipelago without islands");
+        winners.fold(first, |best, winner| if winner.score > best.score { winner } else { best })
+    }
+
+    fn chunk_size(&self) -> usize {
+        let threads = max(1, self.threads);
+        max(1, (self.islands.len() + threads - 1) / threads)
+    }
+}
+
+/// Seed of a single island, derived from the seed of the run
+fn island_seed(seed: usize, island: usize) -> usize {
+    StdRng::from_seed(&[seed, island][..]).gen()
+}
+
+/// Summary of the scores on an island
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct IslandStats {
+    pub island: usize,
+    pub generation: u32,
+    pub best: Number,
+    pub mean: Number,
+    pub worst: Number
+}
+
+impl IslandStats {
+    pub fn new<P: Rand+Clone+Source>(island: usize, population: &Population<P>) -> IslandStats {
+        let totals = population.scores.iter().map(|s| s.total_score()).collect::<Vec<Number>>();
+        let n = max(1, totals.len()) as Number;
+        IslandStats {
+            island: island,
+            generation: population.generation,
+            best: totals.iter().cloned().fold(::std::f32::NEG_INFINITY, Number::max),
+            mean: totals.iter().fold(0.0, |a, &b| a + b) / n,
+            worst: totals.iter().cloned().fold(::std::f32::INFINITY, Number::min)
+        }
+    }
+}
+
+impl fmt::Display for IslandStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] island {}: best {}, mean {}, worst {}", self.generation, self.island, self.best, self.mean, self.worst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::evolve::{random_population, Config, Population, ScoreCard};
+    use super::super::selection::Tournament;
+    use super::super::super::structure::*;
+    use super::super::super::serialize::GameTrace;
+    use super::super::super::size::Size;
+    use rand::Rng;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    fn island(programs: Vec<Program>, scores: Vec<Number>) -> Population<Program> {
+        let mut p = Population::new(programs.len(), 1);
+        for (program, score) in programs.into_iter().zip(scores.into_iter()) {
+            p.add(program);
+            p.scores.push(ScoreCard::new(vec![("score", score)], GameTrace::new()));
+        }
+        p
+    }
+
+    #[test]
+    fn ring_migration_replaces_the_worst_of_the_next_island() {
+        let mut archipelago = Archipelago::new(vec![
+            island(vec![thrust!(), left!(), skip!()], vec![3.0, 1.0, 0.0]),
+            island(vec![right!(), left!(), skip!()], vec![2.0, 1.0, 0.0])
+        ]).with_migration(Migration::new().with_migrants(1));
+
+        archipelago.migrate(&mut Config::new().rng(1));
+
+        assert_eq!(vec![thrust!(), left!(), right!()], archipelago.islands[0].population);
+        assert_eq!(vec![right!(), left!(), thrust!()], archipelago.islands[1].population);
+    }
+
+    #[test]
+    fn islands_evolve_the_same_on_any_number_of_threads() {
+        let config = Config::new().with_seed(11);
+        let migration = Migration::new().with_topology(Topology::Random).with_interval(2).with_migrants(2);
+
+        let run = |threads: usize| {
+            let islands = (0..4).map(|i| random_population::<Program, _>(10, &mut config.with_seed(i).rng(0))).collect();
+            let mut archipelago = Archipelago::new(islands).with_migration(migration).with_threads(threads);
+            for _ in 0..5 {
+                archipelago.score(config.seed, |p, rng| ScoreCard::new(vec![("score", rng.gen::<Number>() - p.size() as Number)], GameTrace::new()));
+                archipelago = archipelago.evolve(&config, &Tournament::new(3));
+            }
+            archipelago.islands.iter().map(|island| island.population.clone()).collect::<Vec<Vec<Program>>>()
+        };
+
+        assert_eq!(run(1), run(3));
+    }
+}
@@ -0,0 +1,128 @@
+//! Bounded cache of program scores
+//!
+//! Reproduction copies programs verbatim, so many programs of a generation were already scored
+//! in the previous one. With a deterministic scoring function, their scores can be looked up
+//! by the structural hash of the program instead of running the simulation again.
+use std::collections::{HashMap, VecDeque};
+use super::super::structure::Number;
+use super::evolve::ScoreCard;
+
+/// Scores keyed by structural hash, forgetting the oldest entries when full
+pub struct FitnessCache {
+    capacity: usize,
+    scores: HashMap<u64, ScoreCard>,
+    order: VecDeque<u64>,
+    hits: u64,
+    misses: u64
+}
+
+impl FitnessCache {
+    /// Create a cache that holds at most `capacity` scores
+    ///
+    /// Score cards include their game trace, which should be taken into account when picking
+    /// the capacity.
+    pub fn new(capacity: usize) -> FitnessCache {
+        FitnessCache {
+            capacity: capacity,
+            scores: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            hits: 0,
+            misses: 0
+        }
+    }
+
+    pub fn get(&self, key: u64) -> Option<&ScoreCard> {
+        self.scores.get(&key)
+    }
+
+    pub fn insert(&mut self, key: u64, score: ScoreCard) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.scores.insert(key, score).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.scores.remove(&oldest);
+        }
+    }
+
+    /// Scores for all keys, in order
+    ///
+    /// `compute` gets the indexes of the keys that are not in the cache, one for every distinct
+    /// key, and returns their scores in the same order. Keys that are in the cache, or that occur
+    /// earlier in `keys`, count as hits.
+    pub fn score_all<F>(&mut self, keys: &[u64], compute: F) -> Vec<ScoreCard>
+        where F: FnOnce(&[usize]) -> Vec<ScoreCard>
+    {
+        let mut pending: HashMap<u64, usize> = HashMap::new();
+        let mut misses = Vec::new();
+        for (i, &key) in keys.iter().enumerate() {
+            if self.scores.contains_key(&key) || pending.contains_key(&key) {
+                self.hits += 1;
+            } else {
+                self.misses += 1;
+                pending.insert(key, misses.len());
+                misses.push(i);
+            }
+        }
+
+        let computed = compute(&misses);
+        let scores = keys.iter().map(|key| match pending.get(key) {
+            Some(&m) => computed[m].clone(),
+            None     => self.scores[key].clone()
+        }).collect();
+
+        for (&i, score) in misses.iter().zip(computed.into_iter()) {
+            self.insert(keys[i], score);
+        }
+        scores
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Fraction of lookups since the last `reset_counts` that were answered from the cache
+    pub fn hit_rate(&self) -> Number {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as Number / lookups as Number }
+    }
+
+    pub fn reset_counts(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::evolve::ScoreCard;
+    use super::super::super::serialize::GameTrace;
+
+    fn score(x: Number) -> ScoreCard {
+        ScoreCard::new(vec![("score", x)], GameTrace::new())
+    }
+
+    #[test]
+    fn oldest_scores_are_forgotten() {
+        let mut cache = FitnessCache::new(2);
+        cache.insert(1, score(1.0));
+        cache.insert(2, score(2.0));
+        cache.insert(3, score(3.0));
+
+        assert_eq!(2, cache.len());
+        assert!(cache.get(1).is_none());
+        assert_eq!(Some(3.0), cache.get(3).map(|s| s.total_score()));
+    }
+}
@@ -1,12 +1,44 @@
-//! 
+//! Recombination of two parent trees into two children
+//!
+//! `cross_over` swaps two random subtrees of the same type. The other operators restrict the
+//! subtrees that are swapped, to keep the children from growing much larger than their parents.
 
 use rand;
 
-use super::super::visit::{NodeType, Visitable, BucketCollector};
+use super::super::visit::{NodeType, NodeRef, Visitable, BucketCollector};
 use super::super::structure::{Program, Sensor, Command, Expression, Condition};
+use super::super::size::Size;
 use super::super::copy;
 use super::super::copy::Copyable;
 
+/// The crossover operators that `Population::evolve` can apply
+#[derive(Clone,Copy,Debug,PartialEq,Eq,RustcEncodable,RustcDecodable)]
+pub enum Crossover {
+    /// Swap uniformly chosen subtrees of the same type (`cross_over`)
+    Subtree,
+    /// Swap a subtree for one of at most twice its size plus one (`size_fair`)
+    SizeFair,
+    /// Swap subtrees at a single point of the region where both parents have the same shape
+    /// (`one_point`)
+    OnePoint,
+    /// Swap every node of the common region with probability one half (`uniform`)
+    Uniform
+}
+
+impl Crossover {
+    pub fn cross_over<'a, P, H>(&self, a: &'a P, b: &'a P, rng: &mut H) -> (P, P) where
+        P: Visitable<'a>+Copyable,
+        H: rand::Rng+Sized
+    {
+        match *self {
+            Crossover::Subtree  => cross_over(a, b, rng),
+            Crossover::SizeFair => size_fair(a, b, rng),
+            Crossover::OnePoint => one_point(a, b, rng),
+            Crossover::Uniform  => uniform(a, b, rng)
+        }
+    }
+}
+
 pub fn cross_over<'a, P, H>(a: &'a P, b: &'a P, rng: &mut H) -> (P, P) where 
     P: Visitable<'a>+Copyable,
     H: rand::Rng+Sized
@@ -61,12 +93,249 @@ fn cross_over_command<P: Copyable>(a: &P, b: &P, an: &Command, bn: &Command) ->
      b.copy(&copy::CopyReplaceCommand { to_replace: bn, replace_with: an }))
 }
 
+/// Size-fair crossover
+///
+/// The subtree of `a` is chosen uniformly. The subtree of `b` is chosen among those of the same
+/// type that are at most twice its size plus one, so that a small subtree is never swapped for a
+/// huge one. This is a simplified form of Langdon's size-fair crossover. If `b` has no subtree that
+/// small, the smallest one is taken.
+pub fn size_fair<'a, P, H>(a: &'a P, b: &'a P, rng: &mut H) -> (P, P) where
+    P: Visitable<'a>+Copyable,
+    H: rand::Rng+Sized
+{
+    let mut a_nodes = BucketCollector::new();
+    let mut b_nodes = BucketCollector::new();
+    a.visit(&mut a_nodes);
+    b.visit(&mut b_nodes);
+
+    let nodes = a_nodes.get_counts().into_iter()
+        .zip(b_nodes.get_counts().into_iter())
+        .filter(|&((_, ac), (_, bc))| ac > 0 && bc > 0)
+        .map(|((t, _), (_, _))| t)
+        .collect::<Vec<NodeType>>();
+
+    let picked_type = rng.choose(&nodes).unwrap();
+
+    match *picked_type {
+        NodeType::Program    => { let an = *rng.choose(&a_nodes.programs).unwrap(); cross_over_program(a, b, an, fair_partner(an, &b_nodes.programs, rng)) },
+        NodeType::Expression => { let an = *rng.choose(&a_nodes.expressions).unwrap(); cross_over_expression(a, b, an, fair_partner(an, &b_nodes.expressions, rng)) },
+        NodeType::Condition  => { let an = *rng.choose(&a_nodes.conditions).unwrap(); cross_over_condition(a, b, an, fair_partner(an, &b_nodes.conditions, rng)) },
+        NodeType::Sensor     => cross_over_sensor(a, b, rng.choose(&a_nodes.sensors).unwrap(), rng.choose(&b_nodes.sensors).unwrap()),
+        NodeType::Command    => cross_over_command(a, b, rng.choose(&a_nodes.commands).unwrap(), rng.choose(&b_nodes.commands).unwrap())
+    }
+}
+
+fn fair_partner<'a, T: Size, H: rand::Rng>(an: &T, candidates: &[&'a T], rng: &mut H) -> &'a T {
+    let limit = 2 * an.size() + 1;
+    let fair = candidates.iter().cloned().filter(|c| c.size() <= limit).collect::<Vec<&T>>();
+    match rng.choose(&fair) {
+        Some(&bn) => bn,
+        None      => candidates.iter().cloned().min_by_key(|c| c.size()).unwrap()
+    }
+}
+
+/// One-point crossover after Poli and Langdon
+///
+/// Both parents are walked from the root down, as long as their nodes have the same shape: the
+/// same number and types of arguments. Subtrees are swapped at a point chosen uniformly in this
+/// common region, so the children keep the shape of the parents near the root.
+pub fn one_point<'a, P, H>(a: &'a P, b: &'a P, rng: &mut H) -> (P, P) where
+    P: Visitable<'a>+Copyable,
+    H: rand::Rng+Sized
+{
+    let mut a_nodes = BucketCollector::new();
+    let mut b_nodes = BucketCollector::new();
+    a.visit(&mut a_nodes);
+    b.visit(&mut b_nodes);
+
+    let mut region = Vec::new();
+    common_region(a_nodes.root().unwrap(), b_nodes.root().unwrap(), &mut region);
+
+    match *rng.choose(&region).unwrap() {
+        (NodeRef::Program(an), NodeRef::Program(bn))       => cross_over_program(a, b, an, bn),
+        (NodeRef::Condition(an), NodeRef::Condition(bn))   => cross_over_condition(a, b, an, bn),
+        (NodeRef::Expression(an), NodeRef::Expression(bn)) => cross_over_expression(a, b, an, bn),
+        (NodeRef::Command(an), NodeRef::Command(bn))       => cross_over_command(a, b, an, bn),
+        (NodeRef::Sensor(an), NodeRef::Sensor(bn))         => cross_over_sensor(a, b, an, bn),
+        _ => unreachable!("common region pairs nodes of the same type")
+    }
+}
+
+/// Uniform crossover after Poli and Langdon
+///
+/// Every pair of nodes in the common region (see `one_point`) is swapped with probability one
+/// half. Inside the region only the operator is swapped and the arguments are visited in turn, at
+/// its boundary the whole subtrees are swapped.
+pub fn uniform<'a, P, H>(a: &'a P, b: &'a P, rng: &mut H) -> (P, P) where
+    P: Visitable<'a>+Copyable,
+    H: rand::Rng+Sized
+{
+    let mut a_nodes = BucketCollector::new();
+    let mut b_nodes = BucketCollector::new();
+    a.visit(&mut a_nodes);
+    b.visit(&mut b_nodes);
+
+    match (a_nodes.root().unwrap(), b_nodes.root().unwrap()) {
+        (NodeRef::Program(an), NodeRef::Program(bn)) => {
+            let (x, y) = uniform_program(an, bn, rng);
+            (a.copy(&copy::CopyReplaceProgram { to_replace: an, replace_with: &x }),
+             b.copy(&copy::CopyReplaceProgram { to_replace: bn, replace_with: &y }))
+        },
+        (NodeRef::Condition(an), NodeRef::Condition(bn)) => {
+            let (x, y) = uniform_condition(an, bn, rng);
+            (a.copy(&copy::CopyReplaceCondition { to_replace: an, replace_with: &x }),
+             b.copy(&copy::CopyReplaceCondition { to_replace: bn, replace_with: &y }))
+        },
+        (NodeRef::Expression(an), NodeRef::Expression(bn)) => {
+            let (x, y) = uniform_expression(an, bn, rng);
+            (a.copy(&copy::CopyReplaceExpression { to_replace: an, replace_with: &x }),
+             b.copy(&copy::CopyReplaceExpression { to_replace: bn, replace_with: &y }))
+        },
+        (NodeRef::Command(an), NodeRef::Command(bn)) => {
+            let (x, y) = swap_maybe(an, bn, rng);
+            (a.copy(&copy::CopyReplaceCommand { to_replace: an, replace_with: &x }),
+             b.copy(&copy::CopyReplaceCommand { to_replace: bn, replace_with: &y }))
+        },
+        (NodeRef::Sensor(an), NodeRef::Sensor(bn)) => {
+            let (x, y) = swap_maybe(an, bn, rng);
+            (a.copy(&copy::CopyReplaceSensor { to_replace: an, replace_with: &x }),
+             b.copy(&copy::CopyReplaceSensor { to_replace: bn, replace_with: &y }))
+        },
+        _ => unreachable!("parents of the same type have roots of the same type")
+    }
+}
+
+/// The pairs of nodes at the same position in both trees, as long as the shapes agree
+fn common_region<'a>(a: NodeRef<'a>, b: NodeRef<'a>, region: &mut Vec<(NodeRef<'a>, NodeRef<'a>)>) {
+    region.push((a, b));
+    match (a, b) {
+        (NodeRef::Program(&Program::If(ref ca, ref la, ref ra)), NodeRef::Program(&Program::If(ref cb, ref lb, ref rb))) => {
+            common_region(NodeRef::Condition(ca), NodeRef::Condition(cb), region);
+            common_region(NodeRef::Program(la), NodeRef::Program(lb), region);
+            common_region(NodeRef::Program(ra), NodeRef::Program(rb), region);
+        },
+        (NodeRef::Program(&Program::Command(ref x)), NodeRef::Program(&Program::Command(ref y))) => {
+            common_region(NodeRef::Command(x), NodeRef::Command(y), region);
+        },
+        (NodeRef::Condition(x), NodeRef::Condition(y)) => {
+            if let (&Condition::Not(ref x), &Condition::Not(ref y)) = (x, y) {
+                common_region(NodeRef::Condition(x), NodeRef::Condition(y), region);
+            } else if let (Some((_, la, ra)), Some((_, lb, rb))) = (connective(x), connective(y)) {
+                common_region(NodeRef::Condition(la), NodeRef::Condition(lb), region);
+                common_region(NodeRef::Condition(ra), NodeRef::Condition(rb), region);
+            } else if let (Some((_, la, ra)), Some((_, lb, rb))) = (comparison(x), comparison(y)) {
+                common_region(NodeRef::Expression(la), NodeRef::Expression(lb), region);
+                common_region(NodeRef::Expression(ra), NodeRef::Expression(rb), region);
+            }
+        },
+        (NodeRef::Expression(x), NodeRef::Expression(y)) => {
+            if let (&Expression::Sensor(ref x), &Expression::Sensor(ref y)) = (x, y) {
+                common_region(NodeRef::Sensor(x), NodeRef::Sensor(y), region);
+            } else if let (Some((_, la, ra)), Some((_, lb, rb))) = (arithmetic(x), arithmetic(y)) {
+                common_region(NodeRef::Expression(la), NodeRef::Expression(lb), region);
+                common_region(NodeRef::Expression(ra), NodeRef::Expression(rb), region);
+            }
+        },
+        _ => {}
+    }
+}
+
+fn uniform_program<H: rand::Rng>(a: &Program, b: &Program, rng: &mut H) -> (Program, Program) {
+    match (a, b) {
+        (&Program::If(ref ca, ref la, ref ra), &Program::If(ref cb, ref lb, ref rb)) => {
+            let (ca, cb) = uniform_condition(ca, cb, rng);
+            let (la, lb) = uniform_program(la, lb, rng);
+            let (ra, rb) = uniform_program(ra, rb, rng);
+            (Program::If(Box::new(ca), Box::new(la), Box::new(ra)), Program::If(Box::new(cb), Box::new(lb), Box::new(rb)))
+        },
+        (&Program::Command(ref x), &Program::Command(ref y)) => {
+            let (x, y) = swap_maybe(&**x, &**y, rng);
+            (Program::Command(Box::new(x)), Program::Command(Box::new(y)))
+        },
+        _ => swap_maybe(a, b, rng)
+    }
+}
+
+fn uniform_condition<H: rand::Rng>(a: &Condition, b: &Condition, rng: &mut H) -> (Condition, Condition) {
+    if let (&Condition::Not(ref x), &Condition::Not(ref y)) = (a, b) {
+        let (x, y) = uniform_condition(x, y, rng);
+        (Condition::Not(Box::new(x)), Condition::Not(Box::new(y)))
+    } else if let (Some((op_a, la, ra)), Some((op_b, lb, rb))) = (connective(a), connective(b)) {
+        let (op_a, op_b) = swap_maybe(&op_a, &op_b, rng);
+        let (la, lb) = uniform_condition(la, lb, rng);
+        let (ra, rb) = uniform_condition(ra, rb, rng);
+        (op_a(Box::new(la), Box::new(ra)), op_b(Box::new(lb), Box::new(rb)))
+    } else if let (Some((op_a, la, ra)), Some((op_b, lb, rb))) = (comparison(a), comparison(b)) {
+        let (op_a, op_b) = swap_maybe(&op_a, &op_b, rng);
+        let (la, lb) = uniform_expression(la, lb, rng);
+        let (ra, rb) = uniform_expression(ra, rb, rng);
+        (op_a(Box::new(la), Box::new(ra)), op_b(Box::new(lb), Box::new(rb)))
+    } else {
+        swap_maybe(a, b, rng)
+    }
+}
+
+fn uniform_expression<H: rand::Rng>(a: &Expression, b: &Expression, rng: &mut H) -> (Expression, Expression) {
+    if let (&Expression::Sensor(ref x), &Expression::Sensor(ref y)) = (a, b) {
+        let (x, y) = swap_maybe(&**x, &**y, rng);
+        (Expression::Sensor(Box::new(x)), Expression::Sensor(Box::new(y)))
+    } else if let (Some((op_a, la, ra)), Some((op_b, lb, rb))) = (arithmetic(a), arithmetic(b)) {
+        let (op_a, op_b) = swap_maybe(&op_a, &op_b, rng);
+        let (la, lb) = uniform_expression(la, lb, rng);
+        let (ra, rb) = uniform_expression(ra, rb, rng);
+        (op_a(Box::new(la), Box::new(ra)), op_b(Box::new(lb), Box::new(rb)))
+    } else {
+        swap_maybe(a, b, rng)
+    }
+}
+
+/// Copies of a and b, swapped with probability one half
+fn swap_maybe<T: Clone, H: rand::Rng>(a: &T, b: &T, rng: &mut H) -> (T, T) {
+    if rng.gen() { (b.clone(), a.clone()) } else { (a.clone(), b.clone()) }
+}
+
+/// Constructor and arguments of a node with two arguments of type A
+type Binary<'a, A, T> = (fn(Box<A>, Box<A>) -> T, &'a A, &'a A);
+
+/// Operator and arguments of `Or` and `And`
+fn connective(c: &Condition) -> Option<Binary<Condition, Condition>> {
+    match *c {
+        Condition::Or(ref l, ref r)  => Some((Condition::Or, l, r)),
+        Condition::And(ref l, ref r) => Some((Condition::And, l, r)),
+        _ => None
+    }
+}
+
+/// Operator and arguments of a comparison
+fn comparison(c: &Condition) -> Option<Binary<Expression, Condition>> {
+    match *c {
+        Condition::Less(ref l, ref r)         => Some((Condition::Less, l, r)),
+        Condition::LessEqual(ref l, ref r)    => Some((Condition::LessEqual, l, r)),
+        Condition::Equal(ref l, ref r)        => Some((Condition::Equal, l, r)),
+        Condition::GreaterEqual(ref l, ref r) => Some((Condition::GreaterEqual, l, r)),
+        Condition::Greater(ref l, ref r)      => Some((Condition::Greater, l, r)),
+        _ => None
+    }
+}
+
+/// Operator and arguments of an arithmetic expression
+fn arithmetic(e: &Expression) -> Option<Binary<Expression, Expression>> {
+    match *e {
+        Expression::Plus(ref l, ref r)     => Some((Expression::Plus, l, r)),
+        Expression::Minus(ref l, ref r)    => Some((Expression::Minus, l, r)),
+        Expression::Multiply(ref l, ref r) => Some((Expression::Multiply, l, r)),
+        Expression::Divide(ref l, ref r)   => Some((Expression::Divide, l, r)),
+        _ => None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
-    mod ast { pub use super::super::super::*; }
+    mod ast { pub use super::super::super::super::structure; }
 
     use super::super::super::structure::Program;
+    use super::super::super::depth::Depth;
     use super::*;
     use rand;
     use rand::Rng;
@@ -81,4 +350,48 @@ mod tests {
             program1 = a;
         }
     }
+
+    fn parents() -> (Program, Program) {
+        (iff!(or!(T!(), less!(vy!(), constant!(1.0))), left!(), iff!(F!(), skip!(), thrust!())),
+         iff!(and!(F!(), greater!(y!(), constant!(2.0))), right!(), iff!(T!(), thrust!(), left!())))
+    }
+
+    #[test]
+    fn crossover_keeps_the_number_of_nodes() {
+        let mut rng = rand::thread_rng();
+        let (a, _) = parents();
+        let b = iff!(not!(equal!(plus!(y!(), constant!(1.0)), fuel!())), iff!(T!(), left!(), right!()), skip!());
+
+        for crossover in &[Crossover::Subtree, Crossover::SizeFair, Crossover::OnePoint, Crossover::Uniform] {
+            for _ in 0..20 {
+                let (x, y) = crossover.cross_over(&a, &b, &mut rng);
+                assert_eq!(a.size() + b.size(), x.size() + y.size());
+            }
+        }
+    }
+
+    #[test]
+    fn common_region_crossover_keeps_the_shape_of_the_parents() {
+        let mut rng = rand::thread_rng();
+        let (a, b) = parents();
+
+        for crossover in &[Crossover::OnePoint, Crossover::Uniform] {
+            for _ in 0..20 {
+                let (x, y) = crossover.cross_over(&a, &b, &mut rng);
+                assert_eq!((a.size(), a.depth()), (x.size(), x.depth()));
+                assert_eq!((b.size(), b.depth()), (y.size(), y.depth()));
+            }
+        }
+    }
+
+    #[test]
+    fn crossover_changes_the_parents() {
+        let mut rng = rand::thread_rng();
+        let (a, b) = parents();
+
+        for crossover in &[Crossover::Subtree, Crossover::SizeFair, Crossover::OnePoint, Crossover::Uniform] {
+            let changed = (0..20).map(|_| crossover.cross_over(&a, &b, &mut rng)).filter(|&(ref x, _)| *x != a).count();
+            assert!(changed > 0, "{:?} never changed the parents", crossover);
+        }
+    }
 }
@@ -1,4 +1,6 @@
-use std::cmp::{Ordering};
+use std::cmp::{Ordering, max};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::thread;
 use super::super::copy;
 use super::super::source::Source;
 use super::super::num::partial_max;
@@ -6,12 +8,177 @@ use super::super::visit::Visitable;
 use super::super::simplify::Simplify;
 use super::super::structure::Number;
 use super::super::serialize::GameTrace;
-use super::mutation;
-use super::crossover;
+use super::super::random::{Generate, Generator, GrammarWeights};
+use super::super::depth::Depth;
+use super::super::size::Size;
+use super::super::hash::structural_hash;
+use super::cache::FitnessCache;
+use super::stats::GenerationStats;
+use super::termination::{Criterion, Progress, Termination};
+use super::mutation::MutationOperators;
+use super::selection::Selection;
+use super::crossover::Crossover;
 use rand;
-use rand::{Rng, Rand};
+use rand::{Rand, SeedableRng, StdRng};
 use std::iter::Iterator;
 use std::ops::Add;
+use std::borrow::Cow;
+use std::hash::Hash;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use rustc_serialize::{json, Encodable, Decodable};
+
+/// Parameters of an evolution run
+///
+/// All random decisions of a run are derived from `seed`, so a run can be repeated exactly by
+/// using the same configuration.
+#[derive(Clone,Copy,Debug,RustcEncodable,RustcDecodable)]
+pub struct Config {
+    pub reproduce_weight: u32,
+    pub mutate_weight: u32,
+    pub crossover_weight: u32,
+    pub weights: GrammarWeights,
+    /// Relative weights of the mutation operators
+    pub mutation_operators: MutationOperators,
+    pub crossover: Crossover,
+    /// Offspring deeper than this are rejected, no limit by default
+    pub max_depth: Option<u32>,
+    /// Offspring with more nodes than this are rejected
+    pub max_size: Option<u32>,
+    /// Number of best programs that are copied unchanged into the next generation
+    pub elitism: usize,
+    pub seed: usize
+}
+
+impl Config {
+    /// Creates a builder for `Config`. It should be used in the following sense:
+    ///
+    /// ```
+    /// let config = ast::darwin::evolve::Config::new().with_elitism(2).with_seed(42);
+    /// ```
+    pub fn new() -> Config {
+        Config {
+            reproduce_weight: 10,
+            mutate_weight: 10,
+            crossover_weight: 10,
+            weights: GrammarWeights::new(),
+            mutation_operators: MutationOperators::new(),
+            crossover: Crossover::Subtree,
+            max_depth: None,
+            max_size: None,
+            elitism: 0,
+            seed: 0
+        }
+    }
+
+    pub fn with_reproduce_weight(self, reproduce_weight: u32) -> Config {
+        Config { reproduce_weight: reproduce_weight, ..self }
+    }
+
+    pub fn with_mutate_weight(self, mutate_weight: u32) -> Config {
+        Config { mutate_weight: mutate_weight, ..self }
+    }
+
+    pub fn with_crossover_weight(self, crossover_weight: u32) -> Config {
+        Config { crossover_weight: crossover_weight, ..self }
+    }
+
+    pub fn with_weights(self, weights: GrammarWeights) -> Config {
+        Config { weights: weights, ..self }
+    }
+
+    pub fn with_mutation_operators(self, mutation_operators: MutationOperators) -> Config {
+        Config { mutation_operators: mutation_operators, ..self }
+    }
+
+    pub fn with_crossover(self, crossover: Crossover) -> Config {
+        Config { crossover: crossover, ..self }
+    }
+
+    pub fn with_max_depth(self, max_depth: u32) -> Config {
+        Config { max_depth: Some(max_depth), ..self }
+    }
+
+    pub fn with_max_size(self, max_size: u32) -> Config {
+        Config { max_size: Some(max_size), ..self }
+    }
+
+    pub fn with_elitism(self, elitism: usize) -> Config {
+        Config { elitism: elitism, ..self }
+    }
+
+    pub fn with_seed(self, seed: usize) -> Config {
+        Config { seed: seed, ..self }
+    }
+
+    /// The random number generator that produces the given generation of this run
+    ///
+    /// Use `rng(0)` to create the initial population and `rng(generation + 1)` to evolve a
+    /// population. Deriving it from the seed and the generation, instead of threading a single
+    /// generator through the whole run, means that a run can be picked up again at any generation.
+    pub fn rng(&self, generation: u32) -> StdRng {
+        StdRng::from_seed(&[self.seed, generation as usize][..])
+    }
+
+    /// Check that every random choice of `Population::evolve` has an alternative to pick
+    pub fn validate(&self) -> Result<(), String> {
+        if self.reproduce_weight + self.mutate_weight + self.crossover_weight == 0 {
+            return Err("reproduce, mutate and crossover weights are all 0".to_string());
+        }
+        if self.mutate_weight > 0 {
+            try!(self.mutation_operators.validate());
+        }
+        self.weights.validate()
+    }
+
+    /// Whether a program stays within the depth and size limits
+    pub fn fits<P: Depth+Size>(&self, program: &P) -> bool {
+        self.max_depth.map_or(true, |d| program.depth() <= d) &&
+            self.max_size.map_or(true, |s| program.size() <= s)
+    }
+}
+
+/// Number of tries to produce offspring within the limits before falling back to the parent
+const MAX_ATTEMPTS: usize = 10;
+
+/// Parents for a generation, selected in batches of the population size
+struct Parents<'a, 's, P: 'a+Rand+Clone+Source, S: 's+Selection<P>> {
+    population: &'a Population<P>,
+    selection: &'s S,
+    selected: Vec<usize>
+}
+
+impl <'a, 's, P: Rand+Clone+Source, S: Selection<P>> Parents<'a, 's, P, S> {
+    fn new(population: &'a Population<P>, selection: &'s S) -> Parents<'a, 's, P, S> {
+        Parents { population: population, selection: selection, selected: Vec::new() }
+    }
+
+    fn next_i<R: rand::Rng>(&mut self, rng: &mut R) -> usize {
+        if self.selected.is_empty() {
+            self.selected = self.selection.select_i(self.population, self.population.n(), rng);
+        }
+        self.selected.pop().unwrap()
+    }
+
+    fn next<R: rand::Rng>(&mut self, rng: &mut R) -> &'a P {
+        let i = self.next_i(rng);
+        &self.population.population[i]
+    }
+
+    /// Two parents for crossover, which are different programs unless the selection insists
+    fn next_two<R: rand::Rng>(&mut self, rng: &mut R) -> (&'a P, &'a P) {
+        let one = self.next_i(rng);
+        let mut two = self.next_i(rng);
+        for _ in 1..MAX_ATTEMPTS {
+            if one != two { break; }
+            two = self.next_i(rng);
+        }
+        (&self.population.population[one], &self.population.population[two])
+    }
+}
 
 /// A population with the root of the indicated type
 pub struct Population<P: Rand+Clone+Source> {
@@ -22,7 +189,19 @@ pub struct Population<P: Rand+Clone+Source> {
     pub generation: u32,
 
     /// Collection of fitness scores
-    pub scores: Vec<ScoreCard>
+    pub scores: Vec<ScoreCard>,
+
+    /// How the programs were produced from the previous generation
+    pub operators: OperatorCounts
+}
+
+/// Number of programs that each operator contributed to a generation
+#[derive(Clone,Copy,Debug,Default,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct OperatorCounts {
+    pub elites: usize,
+    pub reproductions: usize,
+    pub mutations: usize,
+    pub crossovers: usize
 }
 
 impl <P: Rand+Clone+Source> Population<P> {
@@ -33,7 +212,8 @@ impl <P: Rand+Clone+Source> Population<P> {
         Population {
             population: Vec::with_capacity(n),
             scores: Vec::with_capacity(n),
-            generation: generation
+            generation: generation,
+            operators: OperatorCounts::default()
         }
     }
 
@@ -50,10 +230,47 @@ impl <P: Rand+Clone+Source> Population<P> {
     pub fn score<F>(&mut self, scoring_fn: F)
         where F: FnMut(&P) -> ScoreCard
     {
-        // FIXME: Parallelize?
         self.scores = self.population.iter().map(scoring_fn).collect();
     }
 
+    /// Apply a scoring function to the entire population using a pool of worker threads
+    ///
+    /// Every program is scored with its own random number generator, derived from `seed`, the
+    /// generation and the index of the program. The scores are therefore reproducible and do not
+    /// depend on the number of threads.
+    pub fn score_parallel<F>(&mut self, threads: usize, seed: usize, scoring_fn: F)
+        where F: Fn(&P, &mut StdRng) -> ScoreCard + Sync,
+              P: Sync
+    {
+        let generation = self.generation;
+        self.scores = parallel_map(&self.population, threads, |i, program| {
+            let mut rng = program_rng(seed, generation, i);
+            scoring_fn(program, &mut rng)
+        });
+    }
+
+    /// Apply a deterministic scoring function, taking the scores of programs that were scored
+    /// before from `cache`
+    pub fn score_cached<F>(&mut self, cache: &mut FitnessCache, mut scoring_fn: F)
+        where F: FnMut(&P) -> ScoreCard,
+              P: Hash
+    {
+        let population = &self.population;
+        let keys = population.iter().map(structural_hash).collect::<Vec<u64>>();
+        self.scores = cache.score_all(&keys, |misses| misses.iter().map(|&i| scoring_fn(&population[i])).collect());
+    }
+
+    /// Like `score_cached`, scoring the programs that are not in the cache on a pool of worker
+    /// threads
+    pub fn score_parallel_cached<F>(&mut self, threads: usize, cache: &mut FitnessCache, scoring_fn: F)
+        where F: Fn(&P) -> ScoreCard + Sync,
+              P: Hash+Sync
+    {
+        let population = &self.population;
+        let keys = population.iter().map(structural_hash).collect::<Vec<u64>>();
+        self.scores = cache.score_all(&keys, |misses| parallel_map(misses, threads, |_, &i| scoring_fn(&population[i])));
+    }
+
     /// Select a tournament winner from a tournament round of size n
     pub fn select_tournament_winner<R: rand::Rng>(&self, n: usize, rng: &mut R) -> &P {
         self.population.get(self.select_tournament_winner_i(n, rng)).unwrap()
@@ -71,6 +288,46 @@ impl <P: Rand+Clone+Source> Population<P> {
         winner
     }
 
+    /// Score and evolve generations until one of the termination criteria is met
+    ///
+    /// `step` is called once for every generation with the random number generator of that
+    /// generation. It must score the population if it has no scores yet, and may do anything
+    /// else that has to happen between scoring and evolving, like logging. Evolution continues
+    /// with the same random number generator. Returns the last (scored) generation and the
+    /// criterion that ended the run.
+    pub fn run_until<S, F>(self, config: &Config, selection: &S, termination: &Termination, mut step: F) -> (Population<P>, Criterion)
+        where P: for<'a> Visitable<'a>+copy::Copyable+Depth+Size,
+              S: Selection<P>,
+              F: FnMut(&mut Population<P>, &mut StdRng)
+    {
+        let mut population = self;
+        let mut progress = Progress::new();
+        loop {
+            let mut rng = config.rng(population.generation + 1);
+            step(&mut population, &mut rng);
+            progress.update(&population);
+            if let Some(reason) = termination.reached(&progress) {
+                return (population, reason);
+            }
+            population = population.evolve(config, selection, &mut rng);
+        }
+    }
+
+    /// Summary of the scores and programs of a scored population
+    pub fn stats(&self) -> GenerationStats
+        where P: Depth+Size+Hash
+    {
+        GenerationStats::new(self)
+    }
+
+    /// Indexes of the k best programs, best first
+    pub fn best_i(&self, k: usize) -> Vec<usize> {
+        let mut indexes = (0..self.n()).collect::<Vec<usize>>();
+        indexes.sort_by(|&a, &b| self.scores[b].partial_cmp(&self.scores[a]).unwrap_or(Ordering::Equal));
+        indexes.truncate(k);
+        indexes
+    }
+
     /// Return the best program from the population
     pub fn winner(&self) -> CreatureScore<P> {
         let indexes = 0..self.n();
@@ -79,36 +336,69 @@ impl <P: Rand+Clone+Source> Population<P> {
     }
 
     /// Produce a new population of the same size based off the current one
-    pub fn evolve<'a, R: rand::Rng>(&'a self, tournament_size: usize, reproduce_weight: u32, mutate_weight: u32, crossover_weight: u32, rng: &mut R) -> Population<P> 
+    ///
+    /// Parents are chosen by `selection`. Offspring that exceed the depth or size limits of
+    /// `config` are produced again, a few times at most, after which the parent takes their place.
+    ///
+    /// Panics if `config` does not validate.
+    pub fn evolve<'a, S: Selection<P>, R: rand::Rng>(&'a self, config: &Config, selection: &S, rng: &mut R) -> Population<P>
         where P: Visitable<'a>+copy::Copyable // Additional bounds for crossover
+                 +Depth+Size                   // Additional bounds for bloat control
     {
+        if let Err(message) = config.validate() {
+            panic!("invalid evolution config: {}", message);
+        }
+        let mut parents = Parents::new(self, selection);
         let mut ret = Self::new(self.n(), self.generation + 1);
+        for i in self.best_i(config.elitism) {
+            debug!("Elite: {}", self.population[i].source());
+            ret.add(self.population[i].clone());
+            ret.operators.elites += 1;
+        }
         while ret.n() < self.n() {
-            pick![
-                reproduce_weight, {
-                    let winner = self.select_tournament_winner(tournament_size, rng);
+            pick![rng;
+                config.reproduce_weight, {
+                    let winner = parents.next(rng);
                     debug!("Reproduce: {}", winner.source());
                     ret.add(winner.clone());
+                    ret.operators.reproductions += 1;
                 },
-                mutate_weight, {
-                    let winner = self.select_tournament_winner(tournament_size, rng);
-                    let mutation = mutation::mutate(winner, rng);
+                config.mutate_weight, {
+                    let winner = parents.next(rng);
+                    let mut mutation = config.mutation_operators.mutate(winner, &config.weights, rng);
+                    for _ in 1..MAX_ATTEMPTS {
+                        if config.fits(&mutation) { break; }
+                        mutation = config.mutation_operators.mutate(winner, &config.weights, rng);
+                    }
+                    if !config.fits(&mutation) { mutation = winner.clone(); }
+
                     debug!("Mutation: {} into {}", winner.source(), mutation.source());
                     ret.add(mutation);
+                    ret.operators.mutations += 1;
                 },
-                crossover_weight, {
+                config.crossover_weight, {
                     if self.n() < 2 { continue; }
 
-                    let (one, two) = self.pick_two(tournament_size, rng);
+                    let (one, two) = parents.next_two(rng);
 
-                    let (child1, child2) = crossover::cross_over(one, two, rng);
+                    let (mut child1, mut child2) = config.crossover.cross_over(one, two, rng);
+                    for _ in 1..MAX_ATTEMPTS {
+                        if config.fits(&child1) && config.fits(&child2) { break; }
+                        let (c1, c2) = config.crossover.cross_over(one, two, rng);
+                        child1 = c1;
+                        child2 = c2;
+                    }
+                    if !config.fits(&child1) { child1 = one.clone(); }
+                    if !config.fits(&child2) { child2 = two.clone(); }
 
                     debug!("Crossover: {} & {} into {} & {}", one.source(), two.source(), child1.source(), child2.source());
 
                     // We try to insert both children, but only if there's room in the population
                     ret.add(child1);
+                    ret.operators.crossovers += 1;
                     if ret.n() < self.n() {
                         ret.add(child2);
+                        ret.operators.crossovers += 1;
                     }
                 }
             ];
@@ -127,26 +417,117 @@ impl <P: Rand+Clone+Source> Population<P> {
     }
 }
 
-pub type Scores = Vec<(&'static str, Number)>;
+/// Everything needed to pick up an evolution run where it stopped
+///
+/// The random number generator is not stored as such: every generation is produced by
+/// `Config::rng(generation)`, so the seed in `config` together with `generation` restores it.
+/// Game traces are left out of the stored scores to keep checkpoints small.
+#[derive(RustcEncodable,RustcDecodable)]
+struct Checkpoint<P> {
+    config: Config,
+    generation: u32,
+    population: Vec<P>,
+    scores: Vec<ScoreCard>,
+    keeper: OptimumKeeper<P>
+}
+
+impl <P: Rand+Clone+Source+Encodable+Decodable> Population<P> {
+    /// Write the population, together with the run configuration and the best program so far, to
+    /// a checkpoint file
+    ///
+    /// The file is written next to `path` first and then moved in place, so an interrupted write
+    /// never destroys the previous checkpoint.
+    pub fn save_checkpoint(&self, config: &Config, keeper: &OptimumKeeper<P>, path: &Path) -> Result<(), Box<Error>> {
+        let checkpoint = Checkpoint {
+            config: *config,
+            generation: self.generation,
+            population: self.population.clone(),
+            scores: self.scores.iter().map(|s| ScoreCard(s.0.clone(), s.1, GameTrace::new(), s.3.clone(), s.4.clone())).collect(),
+            keeper: OptimumKeeper {
+                best_program: keeper.best_program.clone(),
+                best_score: keeper.best_score.clone(),
+                best_generation: keeper.best_generation
+            }
+        };
+        let encoded = try!(json::encode(&checkpoint));
+        write_atomically(&encoded, path)
+    }
+
+    /// Read a checkpoint written by `save_checkpoint`
+    ///
+    /// Returns the population, the configuration of the run and the best program so far.
+    pub fn load_checkpoint(path: &Path) -> Result<(Population<P>, Config, OptimumKeeper<P>), Box<Error>> {
+        let mut content = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut content));
+        let checkpoint: Checkpoint<P> = try!(json::decode(&content));
+        try!(checkpoint.config.validate());
+
+        let population = Population {
+            population: checkpoint.population,
+            generation: checkpoint.generation,
+            scores: checkpoint.scores,
+            operators: OperatorCounts::default()
+        };
+        Ok((population, checkpoint.config, checkpoint.keeper))
+    }
+}
+
+/// Write a file next to `path` first and then move it in place, so an interrupted write never
+/// destroys the previous version
+pub fn write_atomically(content: &str, path: &Path) -> Result<(), Box<Error>> {
+    let partial = path.with_extension("partial");
+    {
+        let mut f = try!(File::create(&partial));
+        try!(f.write_all(content.as_bytes()));
+        try!(f.sync_all());
+    }
+    try!(fs::rename(&partial, path));
+    Ok(())
+}
+
+/// Name of a score component, usually a string literal but owned after loading a checkpoint
+pub type ScoreName = Cow<'static, str>;
+
+pub type Scores = Vec<(ScoreName, Number)>;
+
+/// Results of a program on the individual test cases, higher is better
+pub type Cases = Vec<Number>;
+
+/// Whether a program landed in each of the scenarios it was run on, in order
+pub type Landings = Vec<bool>;
 
 /// Immutable tagged list of scores
-#[derive(Clone,RustcEncodable)]
-pub struct ScoreCard(Scores, Number, GameTrace);
+///
+/// Next to the scores, a card can hold the result of every test case a program was run on. Case
+/// `i` must mean the same scenario for every program in a population, so that selection methods
+/// like `selection::Lexicase` can compare programs case by case. It can also record in which of
+/// its scenarios the program landed, which `termination::Criterion::LandingStreak` counts.
+#[derive(Clone,RustcEncodable,RustcDecodable)]
+pub struct ScoreCard(Scores, Number, GameTrace, Cases, Landings);
 
 impl ScoreCard {
-    pub fn new(scores: Scores, trace: GameTrace) -> ScoreCard {
+    pub fn new<S: Into<ScoreName>>(scores: Vec<(S, Number)>, trace: GameTrace) -> ScoreCard {
+        let scores: Scores = scores.into_iter().map(|(name, x)| (name.into(), x)).collect();
         let sum = scores.iter().map(|&(_, x)| x).fold(0.0, Add::add);
-        ScoreCard(scores, sum, trace)
+        ScoreCard(scores, sum, trace, Vec::new(), Vec::new())
+    }
+
+    pub fn with_cases(self, cases: Cases) -> ScoreCard {
+        ScoreCard(self.0, self.1, self.2, cases, self.4)
+    }
+
+    pub fn with_landings(self, landings: Landings) -> ScoreCard {
+        ScoreCard(self.0, self.1, self.2, self.3, landings)
     }
 
     pub fn into_trace(self) -> GameTrace {
         self.2
     }
 
-    pub fn add(self, scores: Scores) -> ScoreCard {
+    pub fn add<S: Into<ScoreName>>(self, scores: Vec<(S, Number)>) -> ScoreCard {
         let mut xs = self.0;
-        xs.extend(scores);
-        ScoreCard::new(xs, self.2)
+        xs.extend(scores.into_iter().map(|(name, x)| (name.into(), x)));
+        ScoreCard::new(xs, self.2).with_cases(self.3).with_landings(self.4)
         // Which one is faster? 
         /*
         let all_scores = self.0.into_iter().chain(scores.into_iter()).collect();
@@ -165,6 +546,14 @@ impl ScoreCard {
     pub fn trace(&self) -> &GameTrace {
         &self.2
     }
+
+    pub fn cases(&self) -> &Cases {
+        &self.3
+    }
+
+    pub fn landings(&self) -> &Landings {
+        &self.4
+    }
 }
 
 impl PartialEq for ScoreCard {
@@ -193,11 +582,53 @@ impl <P> CreatureScore<P> {
     }
 }
 
+/// Apply a function to all items using a pool of worker threads, keeping the order of the items
+fn parallel_map<T, S, F>(items: &[T], threads: usize, f: F) -> Vec<S>
+    where T: Sync,
+          S: Send,
+          F: Fn(usize, &T) -> S + Sync
+{
+    let next = AtomicUsize::new(0);
+
+    let mut results = thread::scope(|scope| {
+        let workers = (0..max(1, threads)).map(|_| scope.spawn(|| {
+            let mut results = Vec::new();
+            loop {
+                let i = next.fetch_add(1, AtomicOrdering::Relaxed);
+                if i >= items.len() { return results; }
+
+                results.push((i, f(i, &items[i])));
+            }
+        })).collect::<Vec<_>>();
+
+        workers.into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect::<Vec<(usize, S)>>()
+    });
+
+    results.sort_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Random number generator for scoring a single program in `Population::score_parallel`
+fn program_rng(seed: usize, generation: u32, index: usize) -> StdRng {
+    StdRng::from_seed(&[seed, generation as usize, index][..])
+}
+
 /// Generate a random population of size n
-pub fn random_population<P: Rand+Clone+Source>(n: usize) -> Population<P> {
+pub fn random_population<P: Rand+Clone+Source, R: rand::Rng>(n: usize, rng: &mut R) -> Population<P> {
+    let mut ret = Population::new(n, 0);
+    for _ in 0..n {
+        ret.add(rng.gen());
+    }
+    ret
+}
+
+/// Generate a random population of size n with the depth bounds and method of a `Generator`
+pub fn generated_population<P: Rand+Clone+Source+Generate, R: rand::Rng>(n: usize, generator: &Generator, rng: &mut R) -> Population<P> {
     let mut ret = Population::new(n, 0);
     for _ in 0..n {
-        ret.add(rand::random());
+        ret.add(generator.generate(rng));
     }
     ret
 }
@@ -208,7 +639,14 @@ mod tests {
     use super::super::super::visit::{Visitor, Visitable};
     use super::super::super::structure::*;
     use super::super::super::serialize::*;
+    use super::super::super::depth::Depth;
+    use super::super::super::size::Size;
+    use super::super::selection::Tournament;
+    use super::super::mutation::{MutationOperators, Operator};
     use rand;
+    use rand::Rng;
+    use std::env;
+    use std::fs;
 
     // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
     mod ast { pub use super::super::super::super::structure; }
@@ -237,7 +675,7 @@ mod tests {
 
         score_population(&mut p);
 
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::from_seed(&[1usize][..]);
         // This must always produce the single best one
         assert_eq!(3, p.select_tournament_winner_i(4, &mut rng));
 
@@ -245,6 +683,166 @@ mod tests {
         assert!(p.select_tournament_winner_i(3, &mut rng) >= 2);
     }
 
+    #[test]
+    fn parallel_scoring_does_not_depend_on_thread_count() {
+        let mut p = Population::new(10, 1);
+        for _ in 0..50 {
+            p.add(skip!());
+        }
+
+        let scores = |p: &mut Population<Program>, threads: usize| {
+            p.score_parallel(threads, 42, |_, rng| ScoreCard::new(vec![("random", rng.gen::<Number>())], GameTrace::new()));
+            p.scores.iter().map(|s| s.total_score()).collect::<Vec<Number>>()
+        };
+
+        let single = scores(&mut p, 1);
+        assert_eq!(single, scores(&mut p, 4));
+        assert!(single.iter().any(|&s| s != single[0]));
+    }
+
+    #[test]
+    fn cached_programs_are_not_scored_again() {
+        let mut p = Population::new(10, 1);
+        p.add(left!());
+        p.add(right!());
+        p.add(left!());
+
+        let mut cache = FitnessCache::new(100);
+        let mut calls = 0;
+        p.score_cached(&mut cache, |_| { calls += 1; ScoreCard::new(vec![("score", 1.0)], GameTrace::new()) });
+        p.score_parallel_cached(4, &mut cache, |_| ScoreCard::new(vec![("score", 2.0)], GameTrace::new()));
+
+        assert_eq!(2, calls);
+        assert_eq!(vec![1.0, 1.0, 1.0], p.scores.iter().map(|s| s.total_score()).collect::<Vec<Number>>());
+        assert_eq!(4, cache.hits());
+        assert_eq!(2, cache.misses());
+    }
+
+    #[test]
+    fn runs_with_the_same_seed_are_identical() {
+        let config = Config::new().with_seed(42);
+
+        let run = || {
+            let mut p = random_population::<Program, _>(20, &mut config.rng(0));
+            for _ in 0..5 {
+                score_population(&mut p);
+                p = p.evolve(&config, &Tournament::new(3), &mut config.rng(p.generation + 1));
+            }
+            p.population.iter().map(|x| x.source()).collect::<Vec<String>>()
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn checkpoints_restore_the_run() {
+        let config = Config::new().with_seed(7);
+        let mut p = random_population::<Program, _>(10, &mut config.rng(0));
+        score_population(&mut p);
+        let mut keeper = OptimumKeeper::new();
+        {
+            let winner = p.winner();
+            keeper.improved(&winner.program, &winner.score, p.generation);
+        }
+
+        let path = env::temp_dir().join("ast-checkpoint-test.json");
+        p.save_checkpoint(&config, &keeper, &path).unwrap();
+        let (q, loaded_config, loaded_keeper) = Population::<Program>::load_checkpoint(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(p.population, q.population);
+        assert_eq!(p.generation, q.generation);
+        assert_eq!(p.scores.iter().map(|s| s.total_score()).collect::<Vec<Number>>(),
+                   q.scores.iter().map(|s| s.total_score()).collect::<Vec<Number>>());
+        assert_eq!(config.seed, loaded_config.seed);
+        assert_eq!(keeper.best_program, loaded_keeper.best_program);
+    }
+
+    #[test]
+    fn evolution_respects_the_size_limits() {
+        let config = Config::new().with_max_depth(4).with_max_size(12).with_seed(3);
+        let generator = Generator::new().with_max_depth(4);
+
+        let mut p = generated_population::<Program, _>(30, &generator, &mut config.rng(0));
+        p.population.retain(|x| config.fits(x));
+        for _ in 0..10 {
+            score_population(&mut p);
+            p = p.evolve(&config, &Tournament::new(3), &mut config.rng(p.generation + 1));
+        }
+
+        assert!(p.n() > 0);
+        assert!(p.population.iter().all(|x| x.depth() <= 4 && x.size() <= 12));
+    }
+
+    #[test]
+    fn elites_survive_unchanged() {
+        let config = Config::new().with_elitism(2).with_seed(5);
+        let mut p = Population::new(10, 1);
+        p.add(skip!());
+        p.add(right!());
+        p.add(thrust!());
+        p.add(left!());
+        score_population(&mut p);
+
+        let q = p.evolve(&config, &Tournament::new(2), &mut config.rng(2));
+
+        assert_eq!(vec![thrust!(), right!()], q.population[0..2].to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "all mutation operators have weight 0")]
+    fn evolution_refuses_mutation_without_operators() {
+        let operators = MutationOperators::only(Operator::Subtree).with_weight(Operator::Subtree, 0);
+        let config = Config::new().with_mutation_operators(operators);
+        let mut p = Population::new(2, 1);
+        p.add(skip!());
+        p.add(thrust!());
+        score_population(&mut p);
+
+        p.evolve(&config, &Tournament::new(2), &mut config.rng(2));
+    }
+
+    #[test]
+    fn run_stops_at_the_first_criterion_met() {
+        let config = Config::new().with_seed(3);
+        let p = random_population::<Program, _>(10, &mut config.rng(0));
+        let termination = Termination::new().with_target_score(1000.0).with_max_generations(3);
+
+        let mut scored = Vec::new();
+        let (last, reason) = p.run_until(&config, &Tournament::new(2), &termination, |p, _| {
+            scored.push(p.generation);
+            score_population(p);
+        });
+
+        assert_eq!(Criterion::MaxGenerations(3), reason);
+        assert_eq!(3, last.generation);
+        assert_eq!(vec![0, 1, 2, 3], scored);
+    }
+
+    #[test]
+    fn hall_of_fame_keeps_the_best_distinct_programs() {
+        let mut p = Population::new(10, 1);
+        p.add(thrust!());
+        p.add(iff!(T!(), thrust!(), skip!()));
+        p.add(left!());
+        p.add(right!());
+        p.add(skip!());
+        score_population(&mut p);
+
+        let mut hall = HallOfFame::new(3);
+        assert!(hall.update(&p));
+
+        assert_eq!(vec![thrust!(), right!(), left!()],
+                   hall.members().iter().map(|m| m.program.clone()).collect::<Vec<Program>>());
+        assert!(!hall.consider(&skip!(), &ScoreCard::new(vec![("score", 0.0)], GameTrace::new()), 2));
+
+        let path = env::temp_dir().join("ast-hall-of-fame-test.json");
+        hall.save(&path).unwrap();
+        let loaded = HallOfFame::<Program>::load(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(hall.best().unwrap().program, loaded.best().unwrap().program);
+    }
+
     fn score_population(p: &mut Population<Program>) {
         p.score(|x| { 
             let mut c = CommandCounter::new();
@@ -281,6 +879,7 @@ mod tests {
     }
 }
 
+#[derive(RustcEncodable,RustcDecodable)]
 pub struct OptimumKeeper<P> {
     best_program: Option<P>,
     best_score: Option<ScoreCard>,
@@ -303,3 +902,92 @@ impl <P: Simplify+Clone> OptimumKeeper<P> {
         }
     }
 }
+
+/// A program in the `HallOfFame`
+#[derive(Clone,RustcEncodable,RustcDecodable)]
+pub struct Famous<P> {
+    pub program: P,
+    pub score: ScoreCard,
+    /// Generation in which the program reached its score
+    pub generation: u32
+}
+
+/// The best distinct programs of a run
+///
+/// Programs are simplified before they are compared, so programs that only differ in dead
+/// branches are counted once. A program that is seen again keeps its best score.
+#[derive(Clone,RustcEncodable,RustcDecodable)]
+pub struct HallOfFame<P> {
+    capacity: usize,
+    members: Vec<Famous<P>>
+}
+
+impl <P: Simplify+Clone+PartialEq> HallOfFame<P> {
+    /// Create a hall of fame that keeps the best `capacity` programs
+    pub fn new(capacity: usize) -> HallOfFame<P> {
+        HallOfFame { capacity: capacity, members: Vec::with_capacity(capacity + 1) }
+    }
+
+    /// Consider a single program for the hall of fame, returns whether it was admitted
+    pub fn consider(&mut self, program: &P, score: &ScoreCard, generation: u32) -> bool {
+        if self.capacity == 0 || (self.is_full() && score <= &self.members[self.members.len() - 1].score) {
+            return false;
+        }
+
+        let program = program.simplify();
+        if let Some(i) = self.members.iter().position(|m| m.program == program) {
+            if score <= &self.members[i].score {
+                return false;
+            }
+            self.members.remove(i);
+        }
+
+        let i = self.members.iter().position(|m| score > &m.score).unwrap_or(self.members.len());
+        self.members.insert(i, Famous { program: program, score: score.clone(), generation: generation });
+        self.members.truncate(self.capacity);
+        true
+    }
+
+    /// Consider all programs of a scored population, returns whether any was admitted
+    pub fn update(&mut self, population: &Population<P>) -> bool
+        where P: Rand+Source
+    {
+        let mut admitted = false;
+        for (program, score) in population.population.iter().zip(population.scores.iter()) {
+            admitted |= self.consider(program, score, population.generation);
+        }
+        admitted
+    }
+
+    /// The members of the hall of fame, best first
+    pub fn members(&self) -> &[Famous<P>] {
+        &self.members
+    }
+
+    pub fn best(&self) -> Option<&Famous<P>> {
+        self.members.first()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= self.capacity
+    }
+}
+
+impl <P: Encodable+Decodable> HallOfFame<P> {
+    /// Write the hall of fame to a JSON file
+    pub fn save(&self, path: &Path) -> Result<(), Box<Error>> {
+        let encoded = try!(json::encode(self));
+        write_atomically(&encoded, path)
+    }
+
+    /// Read a hall of fame written by `save`
+    pub fn load(path: &Path) -> Result<HallOfFame<P>, Box<Error>> {
+        let mut content = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut content));
+        Ok(try!(json::decode(&content)))
+    }
+}
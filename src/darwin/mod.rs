@@ -1,3 +1,10 @@
+pub mod archipelago;
+pub mod cache;
 pub mod crossover;
 pub mod mutation;
 pub mod evolve;
+pub mod nsga2;
+pub mod optimise;
+pub mod selection;
+pub mod stats;
+pub mod termination;
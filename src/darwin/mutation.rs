@@ -7,13 +7,22 @@ extern crate rand;
 use self::rand::Rng;
 use self::rand::Rand;
 use super::super::structure::{Program, Sensor, Command, Expression, Condition, Number};
-use super::super::visit::{NodeType, Visitable, BucketCollector};
+use super::super::visit::{NodeType, NodeRef, Visitable, BucketCollector};
 use super::super::copy;
 use super::super::copy::Copyable;
+use super::super::random::{GrammarWeights, Weighted};
 
 pub fn mutate<'a, T, R>(a: &'a T, rng: &mut R) -> T where 
     T: Visitable<'a>+Copyable,
     R: rand::Rng+Sized
+{
+    mutate_with_weights(a, &GrammarWeights::new(), rng)
+}
+
+/// Mutate a random node, drawing new nodes according to `weights`
+pub fn mutate_with_weights<'a, T, R>(a: &'a T, weights: &GrammarWeights, rng: &mut R) -> T where 
+    T: Visitable<'a>+Copyable,
+    R: rand::Rng+Sized
 {
     let mut nodes = BucketCollector::new();
     a.visit(&mut nodes);
@@ -26,128 +35,509 @@ pub fn mutate<'a, T, R>(a: &'a T, rng: &mut R) -> T where
     let picked_type = rng.choose(&t_nodes).unwrap();
 
     match *picked_type {
-        NodeType::Program    => mut_program(a, rng.choose(&nodes.programs).unwrap(), rng),
-        NodeType::Expression => mut_expression(a, rng.choose(&nodes.expressions).unwrap(), rng),
-        NodeType::Condition  => mut_condition(a, rng.choose(&nodes.conditions).unwrap(), rng),
-        NodeType::Sensor     => mut_sensor(a, rng.choose(&nodes.sensors).unwrap(), rng),
-        NodeType::Command    => mut_command(a, rng.choose(&nodes.commands).unwrap(), rng)
+        NodeType::Program    => mut_program(a, rng.choose(&nodes.programs).unwrap(), weights, rng),
+        NodeType::Expression => mut_expression(a, rng.choose(&nodes.expressions).unwrap(), weights, rng),
+        NodeType::Condition  => mut_condition(a, rng.choose(&nodes.conditions).unwrap(), weights, rng),
+        NodeType::Sensor     => mut_sensor(a, rng.choose(&nodes.sensors).unwrap(), weights, rng),
+        NodeType::Command    => mut_command(a, rng.choose(&nodes.commands).unwrap(), weights, rng)
     }
 }
 
-fn mut_program<P: Copyable, R: rand::Rng>(a: &P, an: &Program, rng: &mut R) -> P {
-    let bn = Program::rand(rng);
+fn mut_program<P: Copyable, R: rand::Rng>(a: &P, an: &Program, weights: &GrammarWeights, rng: &mut R) -> P {
+    let bn = Program::weighted(weights, rng);
     // FIXME: Also do controlled_mutate_program
     a.copy(&copy::CopyReplaceProgram { to_replace: an, replace_with: &bn })
 }
 
-fn mut_expression<P: Copyable, R: rand::Rng>(a: &P, an: &Expression, rng: &mut R) -> P {
-    let bn = pick![
-        1, Expression::rand(rng),
-        4, controlled_mutate_expression(an, rng)
+fn mut_expression<P: Copyable, R: rand::Rng>(a: &P, an: &Expression, weights: &GrammarWeights, rng: &mut R) -> P {
+    let bn = pick![rng;
+        weights.mutation.replace_subtree, Expression::weighted(weights, rng),
+        weights.mutation.controlled,      controlled_mutate_expression(an, weights, rng)
         ];
     a.copy(&copy::CopyReplaceExpression { to_replace: an, replace_with: &bn })
 }
 
-fn mut_condition<P: Copyable, R: rand::Rng>(a: &P, an: &Condition, rng: &mut R) -> P {
-    let bn = pick![
-        1, Condition::rand(rng),
-        4, controlled_mutate_condition(an, rng)
+fn mut_condition<P: Copyable, R: rand::Rng>(a: &P, an: &Condition, weights: &GrammarWeights, rng: &mut R) -> P {
+    let bn = pick![rng;
+        weights.mutation.replace_subtree, Condition::weighted(weights, rng),
+        weights.mutation.controlled,      controlled_mutate_condition(an, weights, rng)
         ];
     a.copy(&copy::CopyReplaceCondition { to_replace: an, replace_with: &bn })
 }
 
-fn mut_sensor<P: Copyable, R: rand::Rng>(a: &P, an: &Sensor, rng: &mut R) -> P {
-    let bn = Sensor::rand(rng);
+fn mut_sensor<P: Copyable, R: rand::Rng>(a: &P, an: &Sensor, weights: &GrammarWeights, rng: &mut R) -> P {
+    let bn = Sensor::weighted(weights, rng);
     a.copy(&copy::CopyReplaceSensor { to_replace: an, replace_with: &bn })
 }
 
-fn mut_command<P: Copyable, R: rand::Rng>(a: &P, an: &Command, rng: &mut R) -> P {
-    let bn = Command::rand(rng);
+fn mut_command<P: Copyable, R: rand::Rng>(a: &P, an: &Command, weights: &GrammarWeights, rng: &mut R) -> P {
+    let bn = Command::weighted(weights, rng);
     a.copy(&copy::CopyReplaceCommand { to_replace: an, replace_with: &bn })
 }
 
-fn mutate_constant<R: rand::Rng>(value: Number, rng: &mut R) -> Number {
-    pick![
-        1, Number::rand(rng),                 // Completely new random number
-        7, value * rng.gen_range(0.1, 2.0),   // Twiddled random number
-        2, value + rng.gen_range(-1.0, 1.0)   // Move away from 0
+fn mutate_constant<R: rand::Rng>(value: Number, weights: &GrammarWeights, rng: &mut R) -> Number {
+    let w = &weights.mutation;
+    pick![rng;
+        w.new_constant,   Number::rand(rng),                 // Completely new random number
+        w.scale_constant, value * rng.gen_range(0.1, 2.0),   // Twiddled random number
+        w.shift_constant, value + rng.gen_range(-1.0, 1.0)   // Move away from 0
     ]
 }
 
-fn controlled_mutate_condition<R: rand::Rng>(c: &Condition, rng: &mut R) -> Condition {
+fn controlled_mutate_condition<R: rand::Rng>(c: &Condition, weights: &GrammarWeights, rng: &mut R) -> Condition {
     match *c {
-        Condition::True                       => condition_with_conditions(&[], rng),
-        Condition::False                      => condition_with_conditions(&[], rng),
-        Condition::Not(ref l)                 => condition_with_conditions(&[l], rng),
-        Condition::Or(ref l, ref r)           => condition_with_conditions(&[l, r], rng),
-        Condition::And(ref l, ref r)          => condition_with_conditions(&[l, r], rng),
+        Condition::True                       => condition_with_conditions(c, &[], weights, rng),
+        Condition::False                      => condition_with_conditions(c, &[], weights, rng),
+        Condition::Not(ref l)                 => condition_with_conditions(c, &[l], weights, rng),
+        Condition::Or(ref l, ref r)           => condition_with_conditions(c, &[l, r], weights, rng),
+        Condition::And(ref l, ref r)          => condition_with_conditions(c, &[l, r], weights, rng),
 
-        Condition::Less(ref l, ref r)         => condition_with_expressions(&[l, r], rng),
-        Condition::LessEqual(ref l, ref r)    => condition_with_expressions(&[l, r], rng),
-        Condition::Equal(ref l, ref r)        => condition_with_expressions(&[l, r], rng),
-        Condition::GreaterEqual(ref l, ref r) => condition_with_expressions(&[l, r], rng),
-        Condition::Greater(ref l, ref r)      => condition_with_expressions(&[l, r], rng),
+        Condition::Less(ref l, ref r)         => condition_with_expressions(c, &[l, r], weights, rng),
+        Condition::LessEqual(ref l, ref r)    => condition_with_expressions(c, &[l, r], weights, rng),
+        Condition::Equal(ref l, ref r)        => condition_with_expressions(c, &[l, r], weights, rng),
+        Condition::GreaterEqual(ref l, ref r) => condition_with_expressions(c, &[l, r], weights, rng),
+        Condition::Greater(ref l, ref r)      => condition_with_expressions(c, &[l, r], weights, rng),
     }
 }
 
-fn controlled_mutate_expression<R: rand::Rng>(e: &Expression, rng: &mut R) -> Expression {
+fn controlled_mutate_expression<R: rand::Rng>(e: &Expression, weights: &GrammarWeights, rng: &mut R) -> Expression {
     match *e {
-        Expression::Constant(c)              => Expression::Constant(mutate_constant(c, rng)),
-        Expression::Sensor(_)                => Expression::Sensor(Box::new(Sensor::rand(rng))),
-        Expression::Plus(ref l, ref r)       => expression_with_expressions(&[l, r], rng),
-        Expression::Minus(ref l, ref r)      => expression_with_expressions(&[l, r], rng),
-        Expression::Multiply(ref l, ref r)   => expression_with_expressions(&[l, r], rng),
-        Expression::Divide(ref l, ref r)     => expression_with_expressions(&[l, r], rng)
+        Expression::Constant(c)              => Expression::Constant(mutate_constant(c, weights, rng)),
+        Expression::Sensor(_)                => Expression::Sensor(Box::new(Sensor::weighted(weights, rng))),
+        Expression::Plus(ref l, ref r)       => expression_with_expressions(e, &[l, r], weights, rng),
+        Expression::Minus(ref l, ref r)      => expression_with_expressions(e, &[l, r], weights, rng),
+        Expression::Multiply(ref l, ref r)   => expression_with_expressions(e, &[l, r], weights, rng),
+        Expression::Divide(ref l, ref r)     => expression_with_expressions(e, &[l, r], weights, rng)
     }
 }
 
-fn condition_with_conditions<R: rand::Rng>(cs: &[&Condition], rng: &mut R) -> Condition
+/// Alternatives in a controlled mutation are equally likely, unless switched off in the grammar
+fn on(weight: u32) -> u32 {
+    if weight > 0 { 1 } else { 0 }
+}
+
+/// Pick the index of the first argument: mostly the same, some chance to reverse the arms
+fn first_arm<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> usize {
+    pick![rng; weights.mutation.keep_arms, 0, weights.mutation.swap_arms, 1]
+}
+
+fn condition_with_conditions<R: rand::Rng>(c: &Condition, cs: &[&Condition], weights: &GrammarWeights, rng: &mut R) -> Condition
 {
-    let i = pick![8, 0, 2, 1];  // Mostly the same, some chance to reverse
+    let w = &weights.condition;
+    if on(w.true_literal) + on(w.false_literal) + on(w.not) + on(w.or) + on(w.and) == 0 {
+        return c.clone();
+    }
+
+    let i = first_arm(weights, rng);
     let j = 1 - i;
 
-    pick![
-        1, Condition::True,
-        1, Condition::False,
-        1, Condition::Not(Box::new(pick_or_rand(cs, i, rng))),
-        1, Condition::Or(Box::new(pick_or_rand(cs, i, rng)), Box::new(pick_or_rand(cs, j, rng))),
-        1, Condition::And(Box::new(pick_or_rand(cs, i, rng)), Box::new(pick_or_rand(cs, j, rng)))
+    pick![rng;
+        on(w.true_literal),  Condition::True,
+        on(w.false_literal), Condition::False,
+        on(w.not),           Condition::Not(Box::new(pick_or_rand(cs, i, weights, rng))),
+        on(w.or),            Condition::Or(Box::new(pick_or_rand(cs, i, weights, rng)), Box::new(pick_or_rand(cs, j, weights, rng))),
+        on(w.and),           Condition::And(Box::new(pick_or_rand(cs, i, weights, rng)), Box::new(pick_or_rand(cs, j, weights, rng)))
         ]
 }
 
-fn condition_with_expressions<R: rand::Rng>(es: &[&Expression], rng: &mut R) -> Condition
+fn condition_with_expressions<R: rand::Rng>(c: &Condition, es: &[&Expression], weights: &GrammarWeights, rng: &mut R) -> Condition
 {
-    let i = pick![8, 0, 2, 1];  // Mostly the same, some chance to reverse the arms
+    let w = &weights.condition;
+    if on(w.less) + on(w.less_equal) + on(w.equal) + on(w.greater_equal) + on(w.greater) == 0 {
+        return c.clone();
+    }
+
+    let i = first_arm(weights, rng);
     let j = 1 - i;
 
-    pick![
-        1, Condition::Less(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Condition::LessEqual(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Condition::Equal(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Condition::GreaterEqual(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Condition::Greater(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng)))
+    pick![rng;
+        on(w.less),          Condition::Less(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.less_equal),    Condition::LessEqual(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.equal),         Condition::Equal(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.greater_equal), Condition::GreaterEqual(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.greater),       Condition::Greater(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng)))
         ]
 }
 
-fn expression_with_expressions<R: rand::Rng>(es: &[&Expression], rng: &mut R) -> Expression
+fn expression_with_expressions<R: rand::Rng>(e: &Expression, es: &[&Expression], weights: &GrammarWeights, rng: &mut R) -> Expression
 {
-    let i = pick![8, 0, 2, 1];  // Mostly the same, some chance to reverse the arms
+    let w = &weights.expression;
+    if on(w.plus) + on(w.minus) + on(w.multiply) + on(w.divide) == 0 {
+        return e.clone();
+    }
+
+    let i = first_arm(weights, rng);
     let j = 1 - i;
 
-    pick![
-        1, Expression::Plus(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Expression::Minus(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Expression::Multiply(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng))),
-        1, Expression::Divide(Box::new(pick_or_rand(es, i, rng)), Box::new(pick_or_rand(es, j, rng)))
+    pick![rng;
+        on(w.plus),     Expression::Plus(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.minus),    Expression::Minus(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.multiply), Expression::Multiply(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng))),
+        on(w.divide),   Expression::Divide(Box::new(pick_or_rand(es, i, weights, rng)), Box::new(pick_or_rand(es, j, weights, rng)))
         ]
 }
 
 /// Pick from an array or generate a random new element
-fn pick_or_rand<T: rand::Rand+Clone, R: rand::Rng>(cs: &[&T], i: usize, rng: &mut R) -> T {
+fn pick_or_rand<T: Weighted+Clone, R: rand::Rng>(cs: &[&T], i: usize, weights: &GrammarWeights, rng: &mut R) -> T {
     if i < cs.len() {
         (*cs[i]).clone()
     } else {
-        T::rand(rng)
+        T::weighted(weights, rng)
+    }
+}
+
+/// The mutation operators that `Population::evolve` can apply
+#[derive(Clone,Copy,Debug,PartialEq,Eq,RustcEncodable,RustcDecodable)]
+pub enum Operator {
+    /// Replace a random node by a new random subtree, or change its operator (`mutate`)
+    Subtree,
+    /// Replace the tree by one of its subtrees of the same type
+    Hoist,
+    /// Replace a random non-terminal node by a random terminal
+    Shrink,
+    /// Replace a single node by another of the same arity, keeping its arguments
+    Point,
+    /// Swap the `then` and `else` arms of a random `Program::If`
+    SwapArms,
+    /// Swap the arguments of a random binary condition or expression
+    Permutation
+}
+
+/// Relative weights of the mutation operators
+///
+/// An operator with weight 0 is never used.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct MutationOperators {
+    pub subtree: u32,
+    pub hoist: u32,
+    pub shrink: u32,
+    pub point: u32,
+    pub swap_arms: u32,
+    pub permutation: u32
+}
+
+impl MutationOperators {
+    pub fn new() -> MutationOperators {
+        MutationOperators { subtree: 6, hoist: 1, shrink: 1, point: 2, swap_arms: 1, permutation: 1 }
+    }
+
+    /// Use a single operator only
+    pub fn only(operator: Operator) -> MutationOperators {
+        let none = MutationOperators { subtree: 0, hoist: 0, shrink: 0, point: 0, swap_arms: 0, permutation: 0 };
+        none.with_weight(operator, 1)
+    }
+
+    pub fn with_weight(self, operator: Operator, weight: u32) -> MutationOperators {
+        match operator {
+            Operator::Subtree     => MutationOperators { subtree: weight, ..self },
+            Operator::Hoist       => MutationOperators { hoist: weight, ..self },
+            Operator::Shrink      => MutationOperators { shrink: weight, ..self },
+            Operator::Point       => MutationOperators { point: weight, ..self },
+            Operator::SwapArms    => MutationOperators { swap_arms: weight, ..self },
+            Operator::Permutation => MutationOperators { permutation: weight, ..self }
+        }
+    }
+
+    pub fn weight(&self, operator: Operator) -> u32 {
+        match operator {
+            Operator::Subtree     => self.subtree,
+            Operator::Hoist       => self.hoist,
+            Operator::Shrink      => self.shrink,
+            Operator::Point       => self.point,
+            Operator::SwapArms    => self.swap_arms,
+            Operator::Permutation => self.permutation
+        }
+    }
+
+    /// Check that at least one operator can be picked
+    pub fn validate(&self) -> Result<(), String> {
+        if self.subtree + self.hoist + self.shrink + self.point + self.swap_arms + self.permutation == 0 {
+            return Err("all mutation operators have weight 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// Draw an operator according to the weights
+    pub fn pick<R: rand::Rng>(&self, rng: &mut R) -> Operator {
+        pick![rng;
+            self.subtree,     Operator::Subtree,
+            self.hoist,       Operator::Hoist,
+            self.shrink,      Operator::Shrink,
+            self.point,       Operator::Point,
+            self.swap_arms,   Operator::SwapArms,
+            self.permutation, Operator::Permutation
+            ]
+    }
+
+    /// Mutate with an operator drawn according to the weights
+    pub fn mutate<'a, T, R>(&self, a: &'a T, weights: &GrammarWeights, rng: &mut R) -> T where
+        T: Visitable<'a>+Copyable+Clone,
+        R: rand::Rng+Sized
+    {
+        let operator = self.pick(rng);
+        apply(operator, a, weights, rng)
+    }
+}
+
+/// Mutate with the given operator
+///
+/// Operators that do not apply to the tree, like swapping arms in a tree without
+/// `Program::If`, return an unchanged copy.
+pub fn apply<'a, T, R>(operator: Operator, a: &'a T, weights: &GrammarWeights, rng: &mut R) -> T where
+    T: Visitable<'a>+Copyable+Clone,
+    R: rand::Rng+Sized
+{
+    let mut nodes = BucketCollector::new();
+    a.visit(&mut nodes);
+
+    match operator {
+        Operator::Subtree     => mutate_with_weights(a, weights, rng),
+        Operator::Hoist       => hoist(a, &nodes, rng),
+        Operator::Shrink      => shrink(a, &nodes, weights, rng),
+        Operator::Point       => point(a, &nodes, weights, rng),
+        Operator::SwapArms    => swap_arms(a, &nodes, rng),
+        Operator::Permutation => permutation(a, &nodes, rng)
+    }
+}
+
+/// Pick a bucket and an index in it, uniformly over all elements of all buckets
+fn pick_node<R: rand::Rng>(counts: &[usize], rng: &mut R) -> Option<(usize, usize)> {
+    let total = counts.iter().sum::<usize>();
+    if total == 0 {
+        return None;
+    }
+    let mut i = rng.gen_range(0, total);
+    for (bucket, &count) in counts.iter().enumerate() {
+        if i < count {
+            return Some((bucket, i));
+        }
+        i -= count;
+    }
+    None
+}
+
+fn hoist<'a, P: Copyable+Clone, R: rand::Rng>(a: &P, nodes: &BucketCollector<'a>, rng: &mut R) -> P {
+    match nodes.root() {
+        Some(NodeRef::Program(root)) => if let Some(&d) = rng.choose(&nodes.programs[1..]) {
+            return a.copy(&copy::CopyReplaceProgram { to_replace: root, replace_with: d });
+        },
+        Some(NodeRef::Condition(root)) => if let Some(&d) = rng.choose(&nodes.conditions[1..]) {
+            return a.copy(&copy::CopyReplaceCondition { to_replace: root, replace_with: d });
+        },
+        Some(NodeRef::Expression(root)) => if let Some(&d) = rng.choose(&nodes.expressions[1..]) {
+            return a.copy(&copy::CopyReplaceExpression { to_replace: root, replace_with: d });
+        },
+        _ => {}
+    }
+    a.clone()
+}
+
+fn shrink<'a, P: Copyable+Clone, R: rand::Rng>(a: &P, nodes: &BucketCollector<'a>, weights: &GrammarWeights, rng: &mut R) -> P {
+    let programs = nodes.programs.iter().cloned().filter(|p| !is_terminal_program(p)).collect::<Vec<&Program>>();
+    let conditions = nodes.conditions.iter().cloned().filter(|c| !is_terminal_condition(c)).collect::<Vec<&Condition>>();
+    let expressions = nodes.expressions.iter().cloned().filter(|e| !is_terminal_expression(e)).collect::<Vec<&Expression>>();
+
+    match pick_node(&[programs.len(), conditions.len(), expressions.len()], rng) {
+        Some((0, i)) => {
+            let terminal = Program::Command(Box::new(Command::weighted(weights, rng)));
+            a.copy(&copy::CopyReplaceProgram { to_replace: programs[i], replace_with: &terminal })
+        },
+        Some((1, i)) => {
+            let w = &weights.condition;
+            if w.true_literal + w.false_literal == 0 { return a.clone(); }
+            let terminal = pick![rng; w.true_literal, Condition::True, w.false_literal, Condition::False];
+            a.copy(&copy::CopyReplaceCondition { to_replace: conditions[i], replace_with: &terminal })
+        },
+        Some((_, i)) => {
+            let w = &weights.expression;
+            if w.constant + w.sensor == 0 { return a.clone(); }
+            let terminal = pick![rng;
+                w.constant, Expression::Constant(Number::rand(rng)),
+                w.sensor,   Expression::Sensor(Box::new(Sensor::weighted(weights, rng)))
+                ];
+            a.copy(&copy::CopyReplaceExpression { to_replace: expressions[i], replace_with: &terminal })
+        },
+        None => a.clone()
+    }
+}
+
+fn point<'a, P: Copyable+Clone, R: rand::Rng>(a: &P, nodes: &BucketCollector<'a>, weights: &GrammarWeights, rng: &mut R) -> P {
+    let counts = [nodes.conditions.len(), nodes.expressions.len(), nodes.sensors.len(), nodes.commands.len()];
+    match pick_node(&counts, rng) {
+        Some((0, i)) => {
+            let an = nodes.conditions[i];
+            a.copy(&copy::CopyReplaceCondition { to_replace: an, replace_with: &point_condition(an, weights, rng) })
+        },
+        Some((1, i)) => {
+            let an = nodes.expressions[i];
+            a.copy(&copy::CopyReplaceExpression { to_replace: an, replace_with: &point_expression(an, weights, rng) })
+        },
+        Some((2, i)) => {
+            let an = nodes.sensors[i];
+            a.copy(&copy::CopyReplaceSensor { to_replace: an, replace_with: &point_sensor(an, weights, rng) })
+        },
+        Some((_, i)) => {
+            let an = nodes.commands[i];
+            a.copy(&copy::CopyReplaceCommand { to_replace: an, replace_with: &point_command(an, weights, rng) })
+        },
+        None => a.clone()
+    }
+}
+
+fn swap_arms<'a, P: Copyable+Clone, R: rand::Rng>(a: &P, nodes: &BucketCollector<'a>, rng: &mut R) -> P {
+    let ifs = nodes.programs.iter().cloned().filter(|p| !is_terminal_program(p)).collect::<Vec<&Program>>();
+    match rng.choose(&ifs) {
+        Some(&an) => {
+            let swapped = match *an {
+                Program::If(ref c, ref l, ref r) => Program::If(c.clone(), r.clone(), l.clone()),
+                _ => an.clone()
+            };
+            a.copy(&copy::CopyReplaceProgram { to_replace: an, replace_with: &swapped })
+        },
+        None => a.clone()
+    }
+}
+
+fn permutation<'a, P: Copyable+Clone, R: rand::Rng>(a: &P, nodes: &BucketCollector<'a>, rng: &mut R) -> P {
+    let conditions = nodes.conditions.iter().cloned().filter(|c| swap_condition(c).is_some()).collect::<Vec<&Condition>>();
+    let expressions = nodes.expressions.iter().cloned().filter(|e| swap_expression(e).is_some()).collect::<Vec<&Expression>>();
+
+    match pick_node(&[conditions.len(), expressions.len()], rng) {
+        Some((0, i)) => a.copy(&copy::CopyReplaceCondition { to_replace: conditions[i], replace_with: &swap_condition(conditions[i]).unwrap() }),
+        Some((_, i)) => a.copy(&copy::CopyReplaceExpression { to_replace: expressions[i], replace_with: &swap_expression(expressions[i]).unwrap() }),
+        None => a.clone()
+    }
+}
+
+fn is_terminal_program(p: &Program) -> bool {
+    match *p {
+        Program::Command(_) => true,
+        _ => false
+    }
+}
+
+fn is_terminal_condition(c: &Condition) -> bool {
+    match *c {
+        Condition::True | Condition::False => true,
+        _ => false
+    }
+}
+
+fn is_terminal_expression(e: &Expression) -> bool {
+    match *e {
+        Expression::Constant(_) | Expression::Sensor(_) => true,
+        _ => false
+    }
+}
+
+/// Index of a weighted alternative other than `current`, if any has weight
+fn other<R: rand::Rng>(current: usize, weights: &[u32], rng: &mut R) -> Option<usize> {
+    let total = weights.iter().enumerate().filter(|&(i, _)| i != current).map(|(_, &w)| w).sum::<u32>();
+    if total == 0 {
+        return None;
+    }
+    let mut x = rng.gen_range(0, total);
+    for (i, &w) in weights.iter().enumerate() {
+        if i == current { continue; }
+        if x < w { return Some(i); }
+        x -= w;
+    }
+    None
+}
+
+fn point_condition<R: rand::Rng>(c: &Condition, weights: &GrammarWeights, rng: &mut R) -> Condition {
+    let w = &weights.condition;
+    let literals = [w.true_literal, w.false_literal];
+    let connectives = [w.or, w.and];
+    let comparisons = [w.less, w.less_equal, w.equal, w.greater_equal, w.greater];
+
+    let replacement = match *c {
+        Condition::True                       => other(0, &literals, rng).map(|_| Condition::False),
+        Condition::False                      => other(1, &literals, rng).map(|_| Condition::True),
+        Condition::Not(_)                     => None,
+        Condition::Or(ref l, ref r)           => other(0, &connectives, rng).map(|_| Condition::And(l.clone(), r.clone())),
+        Condition::And(ref l, ref r)          => other(1, &connectives, rng).map(|_| Condition::Or(l.clone(), r.clone())),
+        Condition::Less(ref l, ref r)         => other(0, &comparisons, rng).map(|i| comparison(i, l, r)),
+        Condition::LessEqual(ref l, ref r)    => other(1, &comparisons, rng).map(|i| comparison(i, l, r)),
+        Condition::Equal(ref l, ref r)        => other(2, &comparisons, rng).map(|i| comparison(i, l, r)),
+        Condition::GreaterEqual(ref l, ref r) => other(3, &comparisons, rng).map(|i| comparison(i, l, r)),
+        Condition::Greater(ref l, ref r)      => other(4, &comparisons, rng).map(|i| comparison(i, l, r))
+    };
+    replacement.unwrap_or_else(|| c.clone())
+}
+
+fn comparison(i: usize, l: &Expression, r: &Expression) -> Condition {
+    let (l, r) = (Box::new(l.clone()), Box::new(r.clone()));
+    match i {
+        0 => Condition::Less(l, r),
+        1 => Condition::LessEqual(l, r),
+        2 => Condition::Equal(l, r),
+        3 => Condition::GreaterEqual(l, r),
+        _ => Condition::Greater(l, r)
+    }
+}
+
+fn point_expression<R: rand::Rng>(e: &Expression, weights: &GrammarWeights, rng: &mut R) -> Expression {
+    let w = &weights.expression;
+    let operators = [w.plus, w.minus, w.multiply, w.divide];
+
+    let replacement = match *e {
+        Expression::Constant(c)            => Some(Expression::Constant(mutate_constant(c, weights, rng))),
+        Expression::Sensor(ref s)          => Some(Expression::Sensor(Box::new(point_sensor(s, weights, rng)))),
+        Expression::Plus(ref l, ref r)     => other(0, &operators, rng).map(|i| arithmetic(i, l, r)),
+        Expression::Minus(ref l, ref r)    => other(1, &operators, rng).map(|i| arithmetic(i, l, r)),
+        Expression::Multiply(ref l, ref r) => other(2, &operators, rng).map(|i| arithmetic(i, l, r)),
+        Expression::Divide(ref l, ref r)   => other(3, &operators, rng).map(|i| arithmetic(i, l, r))
+    };
+    replacement.unwrap_or_else(|| e.clone())
+}
+
+fn arithmetic(i: usize, l: &Expression, r: &Expression) -> Expression {
+    let (l, r) = (Box::new(l.clone()), Box::new(r.clone()));
+    match i {
+        0 => Expression::Plus(l, r),
+        1 => Expression::Minus(l, r),
+        2 => Expression::Multiply(l, r),
+        _ => Expression::Divide(l, r)
+    }
+}
+
+fn point_sensor<R: rand::Rng>(s: &Sensor, weights: &GrammarWeights, rng: &mut R) -> Sensor {
+    let w = &weights.sensor;
+    let sensors = [Sensor::X, Sensor::Y, Sensor::Vx, Sensor::Vy, Sensor::O, Sensor::W, Sensor::Fuel, Sensor::Altitude, Sensor::PadDistance];
+    let current = sensors.iter().position(|x| x == s).unwrap_or(0);
+    other(current, &[w.x, w.y, w.vx, w.vy, w.o, w.w, w.fuel, w.altitude, w.pad_distance], rng).map_or(*s, |i| sensors[i])
+}
+
+fn point_command<R: rand::Rng>(c: &Command, weights: &GrammarWeights, rng: &mut R) -> Command {
+    let w = &weights.command;
+    let commands = [Command::Skip, Command::Left, Command::Right, Command::Thrust];
+    let current = commands.iter().position(|x| x == c).unwrap_or(0);
+    other(current, &[w.skip, w.left, w.right, w.thrust], rng).map_or(*c, |i| commands[i])
+}
+
+/// The condition with its two arguments swapped, if it has two
+fn swap_condition(c: &Condition) -> Option<Condition> {
+    match *c {
+        Condition::Or(ref l, ref r)           => Some(Condition::Or(r.clone(), l.clone())),
+        Condition::And(ref l, ref r)          => Some(Condition::And(r.clone(), l.clone())),
+        Condition::Less(ref l, ref r)         => Some(Condition::Less(r.clone(), l.clone())),
+        Condition::LessEqual(ref l, ref r)    => Some(Condition::LessEqual(r.clone(), l.clone())),
+        Condition::Equal(ref l, ref r)        => Some(Condition::Equal(r.clone(), l.clone())),
+        Condition::GreaterEqual(ref l, ref r) => Some(Condition::GreaterEqual(r.clone(), l.clone())),
+        Condition::Greater(ref l, ref r)      => Some(Condition::Greater(r.clone(), l.clone())),
+        _ => None
+    }
+}
+
+/// The expression with its two arguments swapped, if it has two
+fn swap_expression(e: &Expression) -> Option<Expression> {
+    match *e {
+        Expression::Plus(ref l, ref r)     => Some(Expression::Plus(r.clone(), l.clone())),
+        Expression::Minus(ref l, ref r)    => Some(Expression::Minus(r.clone(), l.clone())),
+        Expression::Multiply(ref l, ref r) => Some(Expression::Multiply(r.clone(), l.clone())),
+        Expression::Divide(ref l, ref r)   => Some(Expression::Divide(r.clone(), l.clone())),
+        _ => None
     }
 }
 
@@ -155,6 +545,27 @@ fn pick_or_rand<T: rand::Rand+Clone, R: rand::Rng>(cs: &[&T], i: usize, rng: &mu
 mod tests {
     use super::*;
     use structure::{Expression,Sensor};
+    use random::SensorWeights;
+    use size::Size;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    #[test]
+    fn should_not_introduce_switched_off_sensors() {
+        let mut rng = super::rand::thread_rng();
+        let mut weights = GrammarWeights::new();
+        weights.sensor = SensorWeights { x: 0, y: 1, vx: 0, vy: 0, o: 0, w: 0, fuel: 0, altitude: 0, pad_distance: 0 };
+
+        let mut expression: Expression = Expression::Sensor(Box::new(Sensor::Y));
+        for _ in 0..100 {
+            expression = mutate_with_weights(&expression, &weights, &mut rng);
+        }
+
+        let mut nodes = BucketCollector::new();
+        expression.visit(&mut nodes);
+        assert!(nodes.sensors.into_iter().all(|&s| s == Sensor::Y));
+    }
 
     #[test]
     fn should_mutate_expression_without_panicing() {
@@ -165,4 +576,41 @@ mod tests {
         let sensor: Expression = Expression::Sensor(Box::new(Sensor::Vy));
         let _ = mutate(&sensor, &mut rng);
     }
+
+    #[test]
+    fn operators_change_the_tree_as_named() {
+        let mut rng = super::rand::thread_rng();
+        let weights = GrammarWeights::new();
+        let program = iff!(less!(vy!(), constant!(1.0)), left!(), iff!(T!(), thrust!(), skip!()));
+
+        for _ in 0..20 {
+            let hoisted = apply(Operator::Hoist, &program, &weights, &mut rng);
+            assert!([left!(), iff!(T!(), thrust!(), skip!()), thrust!(), skip!()].contains(&hoisted));
+
+            let shrunk = apply(Operator::Shrink, &program, &weights, &mut rng);
+            assert!(shrunk.size() < program.size());
+
+            let pointed = apply(Operator::Point, &program, &weights, &mut rng);
+            assert_eq!(program.size(), pointed.size());
+
+            let swapped = apply(Operator::SwapArms, &program, &weights, &mut rng);
+            assert!(swapped == iff!(less!(vy!(), constant!(1.0)), iff!(T!(), thrust!(), skip!()), left!()) ||
+                    swapped == iff!(less!(vy!(), constant!(1.0)), left!(), iff!(T!(), skip!(), thrust!())));
+
+            let permuted = apply(Operator::Permutation, &program, &weights, &mut rng);
+            assert_eq!(iff!(less!(constant!(1.0), vy!()), left!(), iff!(T!(), thrust!(), skip!())), permuted);
+        }
+    }
+
+    #[test]
+    fn switched_off_operators_are_never_picked() {
+        let mut rng = super::rand::thread_rng();
+        let operators = MutationOperators::only(Operator::Point).with_weight(Operator::Hoist, 3);
+
+        for _ in 0..100 {
+            let operator = operators.pick(&mut rng);
+            assert!(operator == Operator::Point || operator == Operator::Hoist);
+        }
+        assert!(MutationOperators::only(Operator::Point).with_weight(Operator::Point, 0).validate().is_err());
+    }
 }
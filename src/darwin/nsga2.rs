@@ -0,0 +1,273 @@
+//! Multi-objective evolution with NSGA-II
+//!
+//! Instead of the total score, chosen components of the `ScoreCard` are taken as separate
+//! objectives, all of which are maximised. Programs are ranked by non-dominated sorting: the
+//! first front holds the programs that no other program beats on every objective, the second
+//! front those that only the first front beats, and so on. Within a front, programs in sparsely
+//! populated regions are preferred, as measured by the crowding distance.
+//!
+//! A generation consists of two steps:
+//!
+//! 1. `Population::evolve` with `Nsga2` as the selection produces offspring from the parents.
+//! 2. After scoring the offspring, `Nsga2::survivors` keeps the best of parents and offspring.
+use std::cmp::Ordering;
+use std::error::Error;
+use std::path::Path;
+use super::super::source::Source;
+use super::super::structure::Number;
+use super::evolve::{Population, ScoreCard, ScoreName, Famous, write_atomically};
+use super::selection::Selection;
+use rand;
+use rand::Rand;
+use rustc_serialize::{json, Encodable};
+
+/// The objectives of a multi-objective run
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Nsga2 {
+    /// Names of the `ScoreCard` components to maximise
+    pub objectives: Vec<ScoreName>
+}
+
+impl Nsga2 {
+    pub fn new<S: Into<ScoreName>>(objectives: Vec<S>) -> Nsga2 {
+        Nsga2 { objectives: objectives.into_iter().map(|o| o.into()).collect() }
+    }
+
+    /// The objective values of a score card
+    ///
+    /// Components that occur more than once are added up, missing components and NaN count as
+    /// the worst possible value.
+    pub fn values(&self, score: &ScoreCard) -> Vec<Number> {
+        self.objectives.iter().map(|objective| {
+            let mut matches = score.scores().iter().filter(|&&(ref name, _)| name == objective).map(|&(_, x)| x).peekable();
+            let value = if matches.peek().is_some() { matches.sum() } else { ::std::f32::NEG_INFINITY };
+            if value.is_nan() { ::std::f32::NEG_INFINITY } else { value }
+        }).collect()
+    }
+
+    fn rank(&self, scores: &[ScoreCard]) -> Ranking {
+        Ranking::new(scores.iter().map(|s| self.values(s)).collect())
+    }
+
+    /// Keep the best `parents.n()` programs out of the parents and their offspring
+    ///
+    /// Both populations must be scored. The result belongs to the generation of the offspring.
+    pub fn survivors<P: Rand+Clone+Source>(&self, parents: Population<P>, offspring: Population<P>) -> Population<P> {
+        let n = parents.n();
+        let generation = offspring.generation;
+        let operators = offspring.operators;
+        let programs = parents.population.into_iter().chain(offspring.population.into_iter()).collect::<Vec<P>>();
+        let scores = parents.scores.into_iter().chain(offspring.scores.into_iter()).collect::<Vec<ScoreCard>>();
+
+        let ranking = self.rank(&scores);
+        let mut keep = Vec::with_capacity(n);
+        for front in ranking.fronts.iter() {
+            if keep.len() + front.len() <= n {
+                keep.extend(front.iter().cloned());
+            } else {
+                let mut crowded = front.clone();
+                crowded.sort_by(|&a, &b| ranking.crowding[b].partial_cmp(&ranking.crowding[a]).unwrap_or(Ordering::Equal));
+                let room = n - keep.len();
+                keep.extend(crowded.into_iter().take(room));
+            }
+            if keep.len() >= n { break; }
+        }
+        keep.sort();
+
+        let mut ret = Population::new(n, generation);
+        ret.operators = operators;
+        let mut programs = programs.into_iter().map(Some).collect::<Vec<Option<P>>>();
+        for i in keep {
+            ret.add(programs[i].take().unwrap());
+            ret.scores.push(scores[i].clone());
+        }
+        ret
+    }
+
+    /// The programs on the first front of a scored population
+    ///
+    /// The front of a population without scores is empty.
+    pub fn pareto_front<P: Rand+Clone+Source>(&self, population: &Population<P>) -> ParetoFront<P> {
+        let ranking = self.rank(&population.scores);
+        ParetoFront {
+            objectives: self.objectives.clone(),
+            members: ranking.fronts.first().map_or(&[][..], |front| &front[..]).iter().map(|&i| Famous {
+                program: population.population[i].clone(),
+                score: population.scores[i].clone(),
+                generation: population.generation
+            }).collect()
+        }
+    }
+}
+
+/// Binary tournaments on front, then on crowding distance
+impl <P: Rand+Clone+Source> Selection<P> for Nsga2 {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        let ranking = self.rank(&population.scores);
+        let count = population.n();
+        (0..n).map(|_| {
+            let one = rng.gen_range(0, count);
+            let two = rng.gen_range(0, count);
+            if ranking.prefers(one, two) { one } else { two }
+        }).collect()
+    }
+}
+
+/// Programs that are not dominated by any other program of a population
+#[derive(Clone,RustcEncodable,RustcDecodable)]
+pub struct ParetoFront<P> {
+    pub objectives: Vec<ScoreName>,
+    pub members: Vec<Famous<P>>
+}
+
+impl <P: Encodable> ParetoFront<P> {
+    /// Write the front to a JSON file
+    pub fn save(&self, path: &Path) -> Result<(), Box<Error>> {
+        let encoded = try!(json::encode(self));
+        write_atomically(&encoded, path)
+    }
+}
+
+/// Front and crowding distance of every program
+struct Ranking {
+    fronts: Vec<Vec<usize>>,
+    front: Vec<usize>,
+    crowding: Vec<Number>
+}
+
+impl Ranking {
+    fn new(values: Vec<Vec<Number>>) -> Ranking {
+        let fronts = non_dominated_sort(&values);
+        let mut front = vec![0; values.len()];
+        let mut crowding = vec![0.0; values.len()];
+        for (f, members) in fronts.iter().enumerate() {
+            for (&i, distance) in members.iter().zip(crowding_distance(&values, members)) {
+                front[i] = f;
+                crowding[i] = distance;
+            }
+        }
+        Ranking { fronts: fronts, front: front, crowding: crowding }
+    }
+
+    /// Whether program a is preferred over program b
+    fn prefers(&self, a: usize, b: usize) -> bool {
+        self.front[a] < self.front[b] || (self.front[a] == self.front[b] && self.crowding[a] >= self.crowding[b])
+    }
+}
+
+/// Whether a is at least as good as b on every objective and better on at least one
+pub fn dominates(a: &[Number], b: &[Number]) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| x >= y) && a.iter().zip(b.iter()).any(|(x, y)| x > y)
+}
+
+/// Split programs into fronts of mutually non-dominated programs, best front first
+pub fn non_dominated_sort(values: &[Vec<Number>]) -> Vec<Vec<usize>> {
+    let n = values.len();
+    let mut dominated_by = vec![0; n];
+    let mut dominating = vec![Vec::new(); n];
+    for a in 0..n {
+        for b in (a + 1)..n {
+            if dominates(&values[a], &values[b]) {
+                dominating[a].push(b);
+                dominated_by[b] += 1;
+            } else if dominates(&values[b], &values[a]) {
+                dominating[b].push(a);
+                dominated_by[a] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut current = (0..n).filter(|&i| dominated_by[i] == 0).collect::<Vec<usize>>();
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for &a in current.iter() {
+            for &b in dominating[a].iter() {
+                dominated_by[b] -= 1;
+                if dominated_by[b] == 0 {
+                    next.push(b);
+                }
+            }
+        }
+        fronts.push(current);
+        current = next;
+    }
+    fronts
+}
+
+/// Crowding distance of the members of a front, in the order of `front`
+///
+/// The programs at the extremes of an objective get an infinite distance, so they are kept.
+pub fn crowding_distance(values: &[Vec<Number>], front: &[usize]) -> Vec<Number> {
+    let mut distance = vec![0.0; front.len()];
+    let objectives = front.first().map_or(0, |&i| values[i].len());
+    for objective in 0..objectives {
+        let mut order = (0..front.len()).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| values[front[a]][objective].partial_cmp(&values[front[b]][objective]).unwrap_or(Ordering::Equal));
+
+        let value = |k: usize| values[front[order[k]]][objective];
+        let last = front.len() - 1;
+        distance[order[0]] = ::std::f32::INFINITY;
+        distance[order[last]] = ::std::f32::INFINITY;
+
+        let range = value(last) - value(0);
+        if range > 0.0 && range.is_finite() {
+            for k in 1..last {
+                distance[order[k]] += (value(k + 1) - value(k - 1)) / range;
+            }
+        }
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::evolve::{Population, ScoreCard};
+    use super::super::super::structure::*;
+    use super::super::super::serialize::GameTrace;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    fn population(generation: u32, objectives: Vec<(Number, Number)>) -> Population<Program> {
+        let mut p = Population::new(objectives.len(), generation);
+        for (fuel, landing) in objectives {
+            p.add(skip!());
+            p.scores.push(ScoreCard::new(vec![("fuel", fuel), ("landing", landing)], GameTrace::new()));
+        }
+        p
+    }
+
+    #[test]
+    fn programs_are_sorted_into_fronts() {
+        let values = vec![vec![1.0, 1.0], vec![2.0, 0.0], vec![0.0, 0.0], vec![0.0, 2.0]];
+
+        assert_eq!(vec![vec![0, 1, 3], vec![2]], non_dominated_sort(&values));
+    }
+
+    #[test]
+    fn survivors_come_from_the_best_fronts() {
+        let nsga = Nsga2::new(vec!["fuel", "landing"]);
+        let parents = population(1, vec![(0.0, 0.0), (1.0, 1.0), (0.5, 0.5)]);
+        let offspring = population(2, vec![(3.0, 0.0), (0.0, 3.0), (0.2, 0.2)]);
+
+        let survivors = nsga.survivors(parents, offspring);
+
+        assert_eq!(2, survivors.generation);
+        assert_eq!(vec![vec![1.0, 1.0], vec![3.0, 0.0], vec![0.0, 3.0]],
+                   survivors.scores.iter().map(|s| nsga.values(s)).collect::<Vec<Vec<Number>>>());
+    }
+
+    #[test]
+    fn pareto_front_holds_the_non_dominated_programs() {
+        let nsga = Nsga2::new(vec!["fuel", "landing"]);
+        let p = population(1, vec![(1.0, 0.0), (0.0, 0.0), (0.0, 1.0)]);
+
+        let front = nsga.pareto_front(&p);
+
+        assert_eq!(vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+                   front.members.iter().map(|m| nsga.values(&m.score)).collect::<Vec<Vec<Number>>>());
+        assert!(nsga.pareto_front(&population(1, vec![])).members.is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+//! Tune the constants of a program
+//!
+//! Mutation changes constants at random, so a program of the right shape often compares against
+//! badly chosen thresholds. The `Optimiser` searches for better constants with a (1+1) evolution
+//! strategy: all constants are moved by a normally distributed step, and the move is kept when
+//! the score does not get worse. The step size follows the one-fifth success rule, growing after
+//! an improvement and shrinking otherwise.
+//!
+//! The scoring function should judge every candidate on the same scenarios, otherwise the
+//! optimiser chases noise.
+use super::super::constants::Constants;
+use super::super::source::Source;
+use super::super::structure::Number;
+use super::evolve::{Population, ScoreCard};
+use rand;
+use rand::Rand;
+use rand::distributions::normal::StandardNormal;
+
+/// Hill climber for the constants of a program
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Optimiser {
+    /// Number of candidates to score
+    pub iterations: u32,
+    /// Initial step, relative to the size of a constant (or 1 for constants smaller than 1)
+    pub step: Number
+}
+
+impl Optimiser {
+    pub fn new() -> Optimiser {
+        Optimiser { iterations: 100, step: 0.1 }
+    }
+
+    pub fn with_iterations(self, iterations: u32) -> Optimiser {
+        Optimiser { iterations: iterations, ..self }
+    }
+
+    pub fn with_step(self, step: Number) -> Optimiser {
+        Optimiser { step: step, ..self }
+    }
+
+    /// Tune the constants of a program, returns the tuned program with its score
+    pub fn optimise<P, F, R>(&self, program: &P, mut scoring_fn: F, rng: &mut R) -> (P, ScoreCard)
+        where P: Constants+Clone,
+              F: FnMut(&P) -> ScoreCard,
+              R: rand::Rng
+    {
+        let score = scoring_fn(program);
+        self.improve(program, score, scoring_fn, rng)
+    }
+
+    /// Like `optimise`, for a program of which the score is already known
+    pub fn improve<P, F, R>(&self, program: &P, score: ScoreCard, mut scoring_fn: F, rng: &mut R) -> (P, ScoreCard)
+        where P: Constants+Clone,
+              F: FnMut(&P) -> ScoreCard,
+              R: rand::Rng
+    {
+        let mut constants = program.constants();
+        let mut best = (program.clone(), score);
+        if constants.is_empty() {
+            return best;
+        }
+
+        let mut step = self.step;
+        for _ in 0..self.iterations {
+            let candidate_constants = constants.iter().map(|&c| {
+                let StandardNormal(z) = rng.gen();
+                c + step * c.abs().max(1.0) * z as Number
+            }).collect::<Vec<Number>>();
+            let candidate = best.0.with_constants(&candidate_constants);
+            let candidate_score = scoring_fn(&candidate);
+
+            if candidate_score > best.1 {
+                step *= 1.5;
+            } else {
+                step *= 0.9;
+            }
+            if candidate_score >= best.1 {
+                constants = candidate_constants;
+                best = (candidate, candidate_score);
+            }
+        }
+        best
+    }
+
+    /// Tune the constants of the `count` best programs of a scored population in place
+    ///
+    /// Running this every generation makes the evolution Lamarckian: tuned constants are passed
+    /// on to the offspring.
+    pub fn improve_population<P, F, R>(&self, population: &mut Population<P>, count: usize, mut scoring_fn: F, rng: &mut R)
+        where P: Rand+Clone+Source+Constants,
+              F: FnMut(&P) -> ScoreCard,
+              R: rand::Rng
+    {
+        for i in population.best_i(count) {
+            let (program, score) = self.improve(&population.population[i], population.scores[i].clone(), &mut scoring_fn, rng);
+            population.population[i] = program;
+            population.scores[i] = score;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::constants::Constants;
+    use super::super::super::structure::*;
+    use super::super::super::serialize::GameTrace;
+    use rand::{SeedableRng, StdRng};
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    #[test]
+    fn constants_are_tuned_towards_the_optimum() {
+        let distance = |e: &Expression| -e.constants().iter().map(|c| (c - 5.0) * (c - 5.0)).fold(0.0, |a, b| a + b);
+        let mut rng = StdRng::from_seed(&[1usize][..]);
+
+        let (tuned, score) = Optimiser::new().with_iterations(300)
+            .optimise(&plus!(constant!(1.0), constant!(-2.0)), |e| ScoreCard::new(vec![("distance", distance(e))], GameTrace::new()), &mut rng);
+
+        assert!(score.total_score() > -0.01, "tuned to {:?}", tuned);
+        assert_eq!(score.total_score(), distance(&tuned));
+    }
+}
@@ -0,0 +1,377 @@
+//! Strategies to select parents from a scored population
+//!
+//! Every strategy selects a batch of parents at once. This is what stochastic universal sampling
+//! needs, and it lets roulette and rank selection prepare their wheel once per batch instead of
+//! once per parent.
+use std::cmp::{min, Ordering, Reverse};
+use super::super::num::partial_max;
+use super::super::size::Size;
+use super::super::source::Source;
+use super::super::structure::Number;
+use super::evolve::{Population, Cases};
+use rand;
+use rand::Rand;
+
+/// A way to select parents from a scored population
+pub trait Selection<P: Rand+Clone+Source> {
+    /// Indexes of `n` programs selected from `population`, in no particular order
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize>;
+}
+
+/// How the size of programs is weighed in a tournament
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub enum Parsimony {
+    /// Select on fitness alone
+    Off,
+    /// Among equally fit contestants the smallest program wins
+    Lexicographic,
+    /// Every contestant is the winner of a size tournament between two programs, in which the
+    /// smaller one wins with probability `pressure / 2`. Sensible values lie between 1 (no
+    /// pressure) and 2.
+    DoubleTournament(Number)
+}
+
+/// The best of `size` randomly drawn contestants wins
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Tournament {
+    pub size: usize,
+    /// Whether a program can be drawn more than once for the same tournament
+    pub replacement: bool,
+    pub parsimony: Parsimony
+}
+
+impl Tournament {
+    /// A tournament of the given size that draws contestants without replacement
+    pub fn new(size: usize) -> Tournament {
+        Tournament { size: size, replacement: false, parsimony: Parsimony::Off }
+    }
+
+    pub fn with_replacement(self) -> Tournament {
+        Tournament { replacement: true, ..self }
+    }
+
+    pub fn with_parsimony(self, parsimony: Parsimony) -> Tournament {
+        Tournament { parsimony: parsimony, ..self }
+    }
+
+    fn winner_i<P: Rand+Clone+Source+Size, R: rand::Rng>(&self, population: &Population<P>, rng: &mut R) -> usize {
+        let count = population.n();
+        let scores = &population.scores;
+        let sizes = |i: usize| population.population[i].size();
+
+        match self.parsimony {
+            Parsimony::Off => {
+                let (_, winner) = partial_max(self.contestants(count, rng).into_iter().map(|i| (&scores[i], i))).unwrap();
+                winner
+            },
+            Parsimony::Lexicographic => {
+                let (_, _, winner) = partial_max(self.contestants(count, rng).into_iter()
+                    .map(|i| (&scores[i], Reverse(sizes(i)), i))).unwrap();
+                winner
+            },
+            Parsimony::DoubleTournament(pressure) => {
+                let contestants = (0..self.size).map(|_| {
+                    let one = rng.gen_range(0, count);
+                    let two = rng.gen_range(0, count);
+                    let (smaller, larger) = if sizes(one) <= sizes(two) { (one, two) } else { (two, one) };
+                    if rng.gen::<Number>() < pressure / 2.0 { smaller } else { larger }
+                }).collect::<Vec<usize>>();
+                let (_, winner) = partial_max(contestants.into_iter().map(|i| (&scores[i], i))).unwrap();
+                winner
+            }
+        }
+    }
+
+    fn contestants<R: rand::Rng>(&self, count: usize, rng: &mut R) -> Vec<usize> {
+        if self.replacement {
+            (0..self.size).map(|_| rng.gen_range(0, count)).collect()
+        } else {
+            sample(count, self.size, rng)
+        }
+    }
+}
+
+impl <P: Rand+Clone+Source+Size> Selection<P> for Tournament {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        (0..n).map(|_| self.winner_i(population, rng)).collect()
+    }
+}
+
+/// Fitness-proportionate selection
+///
+/// Scores can be negative, so they are measured from the worst score in the population. The
+/// worst program is therefore never selected, unless all programs score the same.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Roulette;
+
+impl <P: Rand+Clone+Source> Selection<P> for Roulette {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        let wheel = Wheel::new(proportional_weights(&population.scores));
+        (0..n).map(|_| wheel.spin(rng.gen_range(0.0, 1.0))).collect()
+    }
+}
+
+/// Stochastic universal sampling
+///
+/// Like `Roulette`, but a single spin places `n` evenly spaced pointers on the wheel. Every
+/// program is selected about as often as its share of the wheel says, with far less variance.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct StochasticUniversalSampling;
+
+impl <P: Rand+Clone+Source> Selection<P> for StochasticUniversalSampling {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        let wheel = Wheel::new(proportional_weights(&population.scores));
+        let start = rng.gen_range(0.0, 1.0);
+        let mut selected = (0..n).map(|k| wheel.spin((start + k as f64) / n as f64)).collect::<Vec<usize>>();
+        rng.shuffle(&mut selected);
+        selected
+    }
+}
+
+/// Linear ranking selection
+///
+/// Selection probability depends on the rank of a program only, not on how much better it is.
+/// The best program is selected `pressure` times as often as an average one and the worst
+/// `2 - pressure` times as often, so `pressure` lies between 1 (uniform) and 2.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Rank {
+    pub pressure: Number
+}
+
+impl Rank {
+    pub fn new(pressure: Number) -> Rank {
+        Rank { pressure: pressure }
+    }
+}
+
+impl <P: Rand+Clone+Source> Selection<P> for Rank {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        let count = population.n();
+        let mut ranked = (0..count).collect::<Vec<usize>>();
+        ranked.sort_by(|&a, &b| population.scores[a].partial_cmp(&population.scores[b]).unwrap_or(Ordering::Equal));
+
+        let pressure = self.pressure as f64;
+        let weights = (0..count).map(|rank| {
+            let position = if count > 1 { rank as f64 / (count - 1) as f64 } else { 0.5 };
+            2.0 - pressure + 2.0 * (pressure - 1.0) * position
+        }).collect();
+
+        let wheel = Wheel::new(weights);
+        (0..n).map(|_| ranked[wheel.spin(rng.gen_range(0.0, 1.0))]).collect()
+    }
+}
+
+/// Select uniformly among the best `fraction` of the population
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Truncation {
+    pub fraction: Number
+}
+
+impl Truncation {
+    pub fn new(fraction: Number) -> Truncation {
+        Truncation { fraction: fraction }
+    }
+}
+
+impl <P: Rand+Clone+Source> Selection<P> for Truncation {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        let count = population.n();
+        let keep = min(count, ((count as Number * self.fraction).ceil() as usize).max(1));
+        let best = population.best_i(keep);
+        (0..n).map(|_| *rng.choose(&best).unwrap()).collect()
+    }
+}
+
+/// Epsilon-lexicase selection
+///
+/// Every selection puts the test cases of `ScoreCard::cases` in a random order. Going through
+/// them, only the programs that are within epsilon of the best result on the case stay in the
+/// running, until a single program is left or the cases run out. Programs that excel at some
+/// cases therefore survive next to each other, even when their total scores are mediocre.
+///
+/// Without a fixed epsilon, the median absolute deviation of the results on a case is used.
+/// Programs without cases are compared on their total score.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Lexicase {
+    pub epsilon: Option<Number>
+}
+
+impl Lexicase {
+    pub fn new() -> Lexicase {
+        Lexicase { epsilon: None }
+    }
+
+    pub fn with_epsilon(self, epsilon: Number) -> Lexicase {
+        Lexicase { epsilon: Some(epsilon) }
+    }
+}
+
+impl <P: Rand+Clone+Source> Selection<P> for Lexicase {
+    fn select_i<R: rand::Rng>(&self, population: &Population<P>, n: usize, rng: &mut R) -> Vec<usize> {
+        let results = population.scores.iter()
+            .map(|s| if s.cases().is_empty() { vec![s.total_score()] } else { s.cases().clone() })
+            .collect::<Vec<Cases>>();
+        let case_count = results.iter().map(|r| r.len()).max().unwrap_or(0);
+        let result = |i: usize, case: usize| results[i].get(case).cloned().unwrap_or(::std::f32::NAN);
+
+        let epsilons = (0..case_count).map(|case| match self.epsilon {
+            Some(epsilon) => epsilon,
+            None          => median_absolute_deviation((0..results.len()).map(|i| result(i, case)).collect())
+        }).collect::<Vec<Number>>();
+
+        (0..n).map(|_| {
+            let mut cases = (0..case_count).collect::<Vec<usize>>();
+            rng.shuffle(&mut cases);
+
+            let mut candidates = (0..results.len()).collect::<Vec<usize>>();
+            for case in cases {
+                if candidates.len() <= 1 { break; }
+
+                let best = partial_max(candidates.iter().map(|&i| result(i, case)).filter(|x| !x.is_nan()));
+                if let Some(best) = best {
+                    candidates.retain(|&i| result(i, case) >= best - epsilons[case]);
+                }
+            }
+            *rng.choose(&candidates).unwrap()
+        }).collect()
+    }
+}
+
+fn median(mut xs: Vec<Number>) -> Number {
+    xs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    if xs.is_empty() { 0.0 } else { xs[xs.len() / 2] }
+}
+
+/// Median distance to the median, ignoring NaN
+fn median_absolute_deviation(xs: Vec<Number>) -> Number {
+    let xs = xs.into_iter().filter(|x| !x.is_nan()).collect::<Vec<Number>>();
+    let m = median(xs.clone());
+    median(xs.into_iter().map(|x| (x - m).abs()).collect())
+}
+
+/// Draw k distinct numbers below count (Floyd's algorithm)
+pub fn sample<R: rand::Rng>(count: usize, k: usize, rng: &mut R) -> Vec<usize> {
+    let k = min(k, count);
+    let mut chosen = Vec::with_capacity(k);
+    for j in (count - k)..count {
+        let t = rng.gen_range(0, j + 1);
+        if chosen.contains(&t) {
+            chosen.push(j);
+        } else {
+            chosen.push(t);
+        }
+    }
+    chosen
+}
+
+/// Scores measured from the worst one, with NaN counting as the worst
+fn proportional_weights(scores: &[super::evolve::ScoreCard]) -> Vec<f64> {
+    let totals = scores.iter().map(|s| s.total_score() as f64).collect::<Vec<f64>>();
+    let worst = totals.iter().cloned().filter(|x| !x.is_nan()).fold(::std::f64::INFINITY, f64::min);
+    totals.into_iter().map(|x| if x.is_nan() { 0.0 } else { x - worst }).collect()
+}
+
+/// A roulette wheel with a slot for every weight
+struct Wheel {
+    cumulative: Vec<f64>
+}
+
+impl Wheel {
+    fn new(weights: Vec<f64>) -> Wheel {
+        let total = weights.iter().fold(0.0, |a, &b| a + b);
+        // Without anything to go on all slots are equally large
+        let weights = if total > 0.0 && total.is_finite() { weights } else { vec![1.0; weights.len()] };
+
+        let mut sum = 0.0;
+        let cumulative = weights.into_iter().map(|w| { sum += w; sum }).collect();
+        Wheel { cumulative: cumulative }
+    }
+
+    /// The slot at a position in [0, 1)
+    fn spin(&self, position: f64) -> usize {
+        let last = self.cumulative.len() - 1;
+        let target = position * self.cumulative[last];
+        // The first slot that ends beyond the target, which skips empty slots
+        let i = match self.cumulative.binary_search_by(|&x| if x <= target { Ordering::Less } else { Ordering::Greater }) {
+            Ok(i) | Err(i) => i
+        };
+        min(i, last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::evolve::{Population, ScoreCard};
+    use super::super::super::structure::*;
+    use super::super::super::serialize::GameTrace;
+    use rand;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    /// Skip, left, right and thrust, scoring 0 to 3
+    fn population() -> Population<Program> {
+        let mut p = Population::new(4, 1);
+        for (program, score) in vec![skip!(), left!(), right!(), thrust!()].into_iter().zip(0..) {
+            p.add(program);
+            p.scores.push(ScoreCard::new(vec![("score", score as Number)], GameTrace::new()));
+        }
+        p
+    }
+
+    fn counts(selected: Vec<usize>) -> Vec<usize> {
+        (0..4).map(|i| selected.iter().filter(|&&s| s == i).count()).collect()
+    }
+
+    #[test]
+    fn full_tournaments_without_replacement_select_the_best() {
+        let mut rng = rand::thread_rng();
+
+        assert!(Tournament::new(4).select_i(&population(), 100, &mut rng).into_iter().all(|i| i == 3));
+    }
+
+    #[test]
+    fn lexicographic_parsimony_prefers_smaller_programs() {
+        let mut p = Population::new(2, 1);
+        p.add(iff!(T!(), skip!(), skip!()));
+        p.add(skip!());
+        p.score(|_| ScoreCard::new(vec![("score", 1.0)], GameTrace::new()));
+
+        let tournament = Tournament::new(2).with_parsimony(Parsimony::Lexicographic);
+        let mut rng = rand::thread_rng();
+        assert!(tournament.select_i(&p, 100, &mut rng).into_iter().all(|i| i == 1));
+    }
+
+    #[test]
+    fn stochastic_universal_sampling_follows_the_scores_exactly() {
+        let mut rng = rand::thread_rng();
+
+        // Measured from the worst program the scores are 0, 1, 2 and 3 out of 6
+        assert_eq!(vec![0, 1, 2, 3], counts(StochasticUniversalSampling.select_i(&population(), 6, &mut rng)));
+    }
+
+    #[test]
+    fn lexicase_selects_specialists_over_generalists() {
+        let mut p = Population::new(3, 1);
+        for cases in vec![vec![10.0, 0.0], vec![0.0, 10.0], vec![5.0, 5.0]] {
+            p.add(skip!());
+            p.scores.push(ScoreCard::new(vec![("score", 10.0)], GameTrace::new()).with_cases(cases));
+        }
+        let mut rng = rand::thread_rng();
+
+        let selected = counts(Lexicase::new().with_epsilon(1.0).select_i(&p, 1000, &mut rng));
+        assert_eq!(0, selected[2]);
+        assert!(selected[0] > 0 && selected[1] > 0);
+    }
+
+    #[test]
+    fn worst_programs_are_never_selected() {
+        let p = population();
+        let mut rng = rand::thread_rng();
+
+        assert_eq!(0, counts(Roulette.select_i(&p, 1000, &mut rng))[0]);
+        assert_eq!(0, counts(Rank::new(2.0).select_i(&p, 1000, &mut rng))[0]);
+        assert_eq!(vec![0, 0], counts(Truncation::new(0.5).select_i(&p, 1000, &mut rng))[0..2].to_vec());
+    }
+}
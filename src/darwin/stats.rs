@@ -0,0 +1,280 @@
+//! Per-generation statistics of an evolution run
+//!
+//! `GenerationStats` summarises a scored population: the spread of the fitness, the shape of
+//! the programs and how the generation was produced. A `StatsLog` appends one row per
+//! generation to a CSV or NDJSON file, which is enough to plot the convergence of a run.
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::Write;
+use std::path::Path;
+use super::super::depth::Depth;
+use super::super::hash::structural_hash;
+use super::super::serialize;
+use super::super::size::Size;
+use super::super::source::Source;
+use super::super::structure::Number;
+use super::cache::FitnessCache;
+use super::evolve::{Population, OperatorCounts};
+use rand::Rand;
+
+/// Summary of a single scored generation
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct GenerationStats {
+    pub generation: u32,
+    pub n: usize,
+
+    /// Total scores, leaving out NaN
+    pub min: Number,
+    pub mean: Number,
+    pub median: Number,
+    pub max: Number,
+    pub std_dev: Number,
+
+    pub mean_depth: Number,
+    pub max_depth: u32,
+
+    /// Distribution of the number of nodes per program
+    pub min_size: u32,
+    pub median_size: u32,
+    pub mean_size: Number,
+    pub max_size: u32,
+
+    /// Fraction of programs whose game trace ends in a landing
+    pub landing_rate: Number,
+    /// Number of structurally different programs
+    pub unique: usize,
+    pub operators: OperatorCounts,
+    pub cache_hit_rate: Option<Number>
+}
+
+impl GenerationStats {
+    pub fn new<P>(population: &Population<P>) -> GenerationStats
+        where P: Rand+Clone+Source+Depth+Size+Hash
+    {
+        let mut totals = population.scores.iter().map(|s| s.total_score()).filter(|x| !x.is_nan()).collect::<Vec<Number>>();
+        totals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let mean = average(totals.iter().cloned());
+        let variance = average(totals.iter().map(|x| (x - mean) * (x - mean)));
+
+        let depths = population.population.iter().map(|p| p.depth()).collect::<Vec<u32>>();
+        let mut sizes = population.population.iter().map(|p| p.size()).collect::<Vec<u32>>();
+        sizes.sort();
+
+        let landings = population.scores.iter()
+            .filter(|s| s.trace().trace().last().map_or(false, |state| state.landed))
+            .count();
+        let unique = population.population.iter().map(structural_hash).collect::<HashSet<u64>>().len();
+
+        GenerationStats {
+            generation: population.generation,
+            n: population.n(),
+            min: totals.first().cloned().unwrap_or(::std::f32::NAN),
+            mean: mean,
+            median: median(&totals).unwrap_or(::std::f32::NAN),
+            max: totals.last().cloned().unwrap_or(::std::f32::NAN),
+            std_dev: variance.sqrt(),
+            mean_depth: average(depths.iter().map(|&d| d as Number)),
+            max_depth: depths.iter().cloned().max().unwrap_or(0),
+            min_size: sizes.first().cloned().unwrap_or(0),
+            median_size: sizes.get(sizes.len() / 2).cloned().unwrap_or(0),
+            mean_size: average(sizes.iter().map(|&s| s as Number)),
+            max_size: sizes.last().cloned().unwrap_or(0),
+            landing_rate: landings as Number / population.scores.len().max(1) as Number,
+            unique: unique,
+            operators: population.operators,
+            cache_hit_rate: None
+        }
+    }
+
+    /// Include the hit rate of the cache the generation was scored with
+    pub fn with_cache(self, cache: &FitnessCache) -> GenerationStats {
+        GenerationStats { cache_hit_rate: Some(cache.hit_rate()), ..self }
+    }
+
+    /// Column names of `csv_row`
+    pub fn csv_header() -> &'static str {
+        "generation,n,min,mean,median,max,std_dev,mean_depth,max_depth,min_size,median_size,mean_size,max_size,\
+         landing_rate,unique,elites,reproductions,mutations,crossovers,cache_hit_rate"
+    }
+
+    /// The statistics as a line of comma separated values, without line ending
+    ///
+    /// A missing cache hit rate is left empty.
+    pub fn csv_row(&self) -> String {
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                self.generation, self.n, self.min, self.mean, self.median, self.max, self.std_dev,
+                self.mean_depth, self.max_depth, self.min_size, self.median_size, self.mean_size, self.max_size,
+                self.landing_rate, self.unique,
+                self.operators.elites, self.operators.reproductions, self.operators.mutations, self.operators.crossovers,
+                self.cache_hit_rate.map_or(String::new(), |rate| rate.to_string()))
+    }
+}
+
+impl fmt::Display for GenerationStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "[{}] score {:.2}/{:.2}/{:.2} (sd {:.2}), size {:.1}, depth {:.1}, landed {:.0}%, unique {}/{}",
+                    self.generation, self.min, self.median, self.max, self.std_dev,
+                    self.mean_size, self.mean_depth, self.landing_rate * 100.0, self.unique, self.n));
+        if let Some(rate) = self.cache_hit_rate {
+            try!(write!(f, ", cache hits {:.0}%", rate * 100.0));
+        }
+        Ok(())
+    }
+}
+
+fn average<I: Iterator<Item=Number>>(values: I) -> Number {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), x| (sum + x, count + 1));
+    if count == 0 { ::std::f32::NAN } else { sum / count as Number }
+}
+
+/// Median of sorted values
+fn median(sorted: &[Number]) -> Option<Number> {
+    let n = sorted.len();
+    if n == 0 {
+        None
+    } else if n % 2 == 1 {
+        Some(sorted[n / 2])
+    } else {
+        Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0)
+    }
+}
+
+/// File format of a `StatsLog`
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub enum Format {
+    /// Comma separated values with a header line
+    Csv,
+    /// One JSON object per line
+    Ndjson
+}
+
+impl Format {
+    /// Guess the format from the extension of a file name, defaulting to CSV
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") | Some("ndjson") | Some("jsonl") => Format::Ndjson,
+            _ => Format::Csv
+        }
+    }
+}
+
+/// Writes one line of statistics per generation
+pub struct StatsLog<W: Write> {
+    format: Format,
+    out: W,
+    /// Whether a CSV header still has to be written before the next row
+    header: bool
+}
+
+impl <W: Write> StatsLog<W> {
+    pub fn new(format: Format, out: W) -> StatsLog<W> {
+        StatsLog { format: format, out: out, header: true }
+    }
+
+    /// Continue a log that already has its header
+    pub fn without_header(self) -> StatsLog<W> {
+        StatsLog { header: false, ..self }
+    }
+
+    pub fn write(&mut self, stats: &GenerationStats) -> Result<(), Box<Error>> {
+        match self.format {
+            Format::Csv => {
+                if self.header {
+                    try!(writeln!(self.out, "{}", GenerationStats::csv_header()));
+                }
+                try!(writeln!(self.out, "{}", stats.csv_row()));
+            },
+            Format::Ndjson => try!(serialize::writeln(stats, &mut self.out))
+        }
+        try!(self.out.flush());
+        self.header = false;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl StatsLog<File> {
+    /// Create (or truncate) a log file, in the format that fits its extension
+    pub fn create(path: &Path) -> Result<StatsLog<File>, Box<Error>> {
+        let file = try!(File::create(path));
+        Ok(StatsLog::new(Format::from_path(path), file))
+    }
+
+    /// Append to a log file, creating it if it doesn't exist
+    ///
+    /// The CSV header is only written to a new or empty file.
+    pub fn append(path: &Path) -> Result<StatsLog<File>, Box<Error>> {
+        let file = try!(OpenOptions::new().append(true).create(true).open(path));
+        let empty = try!(file.metadata()).len() == 0;
+        let log = StatsLog::new(Format::from_path(path), file);
+        Ok(if empty { log } else { log.without_header() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::evolve::{Population, ScoreCard};
+    use super::super::super::structure::*;
+    use super::super::super::serialize::GameTrace;
+    use std::fs::File;
+    use std::io::Read;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    fn population() -> Population<Program> {
+        let mut p = Population::new(4, 3);
+        let programs = vec![skip!(), skip!(), iff!(T!(), left!(), right!()), thrust!()];
+        for (program, score) in programs.into_iter().zip(vec![1.0, 2.0, 4.0, ::std::f32::NAN]) {
+            p.add(program);
+            p.scores.push(ScoreCard::new(vec![("score", score)], GameTrace::new()));
+        }
+        p
+    }
+
+    #[test]
+    fn stats_summarise_scores_and_programs() {
+        let stats = population().stats();
+
+        assert_eq!(3, stats.generation);
+        assert_eq!((1.0, 2.0, 4.0), (stats.min, stats.median, stats.max));
+        assert_eq!(7.0 / 3.0, stats.mean);
+        assert_eq!((2, 2, 6), (stats.min_size, stats.median_size, stats.max_size));
+        assert_eq!(3, stats.unique);
+        assert_eq!(0.0, stats.landing_rate);
+    }
+
+    #[test]
+    fn csv_log_starts_with_a_header() {
+        let stats = population().stats();
+        let mut log = StatsLog::new(Format::Csv, Vec::new());
+        log.write(&stats).unwrap();
+        log.write(&stats).unwrap();
+
+        let text = String::from_utf8(log.into_inner()).unwrap();
+        let lines = text.lines().collect::<Vec<&str>>();
+        assert_eq!(vec![GenerationStats::csv_header(), &stats.csv_row()[..], &stats.csv_row()[..]], lines);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+    }
+
+    #[test]
+    fn appending_keeps_the_rows_and_the_single_header() {
+        let path = ::std::env::temp_dir().join(format!("stats-append-{}.csv", ::std::process::id()));
+        let stats = population().stats();
+        StatsLog::create(&path).unwrap().write(&stats).unwrap();
+        StatsLog::append(&path).unwrap().write(&stats).unwrap();
+
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!(vec![GenerationStats::csv_header(), &stats.csv_row()[..], &stats.csv_row()[..]], text.lines().collect::<Vec<&str>>());
+    }
+}
@@ -0,0 +1,190 @@
+//! When to stop an evolution run
+//!
+//! A `Termination` holds any number of criteria, and a run stops as soon as one of them is met.
+//! The criteria are checked against a `Progress`, which follows a run one scored generation at a
+//! time. `Population::run_until` combines both with scoring and evolution, but runs with a
+//! different loop (like NSGA-II) can use `Progress` directly.
+use std::fmt;
+use std::time::{Duration, Instant};
+use super::super::source::Source;
+use super::super::structure::Number;
+use super::evolve::Population;
+use rand::Rand;
+
+/// A single reason to stop
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Criterion {
+    /// Generation n has been scored
+    MaxGenerations(u32),
+    /// The run has taken at least this long
+    TimeBudget(Duration),
+    /// The best total score has reached the target
+    TargetScore(Number),
+    /// The best total score has not improved for n generations
+    Stagnation(u32),
+    /// The best program of the last generation has landed in n scenarios in a row
+    ///
+    /// The scenarios are taken in order from `ScoreCard::landings`. A score card without landings
+    /// counts as a single scenario, which landed if its game trace ends in a landing.
+    LandingStreak(u32)
+}
+
+impl fmt::Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Criterion::MaxGenerations(n) => write!(f, "reached generation {}", n),
+            Criterion::TimeBudget(d)     => write!(f, "time budget of {}s used up", d.as_secs()),
+            Criterion::TargetScore(x)    => write!(f, "reached target score {}", x),
+            Criterion::Stagnation(n)     => write!(f, "no improvement for {} generations", n),
+            Criterion::LandingStreak(n)  => write!(f, "best program landed {} times in a row", n)
+        }
+    }
+}
+
+/// Criteria of which the first one met ends a run
+#[derive(Clone,Debug,PartialEq)]
+pub struct Termination {
+    pub criteria: Vec<Criterion>
+}
+
+impl Termination {
+    /// A termination that never stops
+    pub fn new() -> Termination {
+        Termination { criteria: Vec::new() }
+    }
+
+    pub fn with(self, criterion: Criterion) -> Termination {
+        let mut criteria = self.criteria;
+        criteria.push(criterion);
+        Termination { criteria: criteria }
+    }
+
+    pub fn with_max_generations(self, generations: u32) -> Termination {
+        self.with(Criterion::MaxGenerations(generations))
+    }
+
+    pub fn with_time_budget(self, budget: Duration) -> Termination {
+        self.with(Criterion::TimeBudget(budget))
+    }
+
+    pub fn with_target_score(self, target: Number) -> Termination {
+        self.with(Criterion::TargetScore(target))
+    }
+
+    pub fn with_stagnation(self, generations: u32) -> Termination {
+        self.with(Criterion::Stagnation(generations))
+    }
+
+    pub fn with_landing_streak(self, generations: u32) -> Termination {
+        self.with(Criterion::LandingStreak(generations))
+    }
+
+    /// The first criterion that the progress meets, if any
+    pub fn reached(&self, progress: &Progress) -> Option<Criterion> {
+        self.criteria.iter().cloned().find(|criterion| progress.meets(criterion))
+    }
+}
+
+/// What a run has achieved so far
+#[derive(Clone,Debug)]
+pub struct Progress {
+    pub started: Instant,
+    /// Last generation seen
+    pub generation: u32,
+    /// Best total score seen so far
+    pub best: Option<Number>,
+    /// Number of generations since the best score improved
+    pub stale: u32,
+    /// Most scenarios in a row in which the best program of the last generation landed
+    pub streak: u32
+}
+
+impl Progress {
+    /// Start following a run now
+    pub fn new() -> Progress {
+        Progress { started: Instant::now(), generation: 0, best: None, stale: 0, streak: 0 }
+    }
+
+    /// Take a scored generation into account
+    pub fn update<P: Rand+Clone+Source>(&mut self, population: &Population<P>) {
+        let winner = population.winner();
+        let score = winner.score.total_score();
+
+        self.generation = population.generation;
+        match self.best {
+            Some(best) if !(score > best) => self.stale += 1,
+            _ => {
+                self.best = Some(score);
+                self.stale = 0;
+            }
+        }
+        let last_landed = [winner.score.trace().trace().last().map_or(false, |state| state.landed)];
+        let landings = if winner.score.landings().is_empty() { &last_landed[..] } else { winner.score.landings() };
+        self.streak = landings.split(|&landed| !landed).map(|run| run.len() as u32).max().unwrap_or(0);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn meets(&self, criterion: &Criterion) -> bool {
+        match *criterion {
+            Criterion::MaxGenerations(n) => self.generation >= n,
+            Criterion::TimeBudget(d)     => self.elapsed() >= d,
+            Criterion::TargetScore(x)    => self.best.map_or(false, |best| best >= x),
+            Criterion::Stagnation(n)     => self.stale >= n,
+            Criterion::LandingStreak(n)  => self.streak >= n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::evolve::{Population, ScoreCard};
+    use super::super::super::structure::*;
+    use super::super::super::serialize::GameTrace;
+    use super::super::super::data::SensorData;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::super::structure; }
+
+    fn generation(generation: u32, score: Number, landed: bool) -> Population<Program> {
+        let mut trace = GameTrace::new();
+        trace.add(&if landed { SensorData::new().landed() } else { SensorData::new() });
+        let mut p = Population::new(1, generation);
+        p.add(skip!());
+        p.scores.push(ScoreCard::new(vec![("score", score)], trace));
+        p
+    }
+
+    #[test]
+    fn landing_streak_counts_the_scenarios_of_the_last_best_program() {
+        let termination = Termination::new().with_landing_streak(3);
+        let mut progress = Progress::new();
+
+        let history = vec![vec![true, false, true], vec![true, true, false], vec![false, true, true, true], vec![true, false]];
+        let reasons = history.into_iter().enumerate().map(|(g, landings)| {
+            let mut p = generation(g as u32, 1.0, false);
+            p.scores[0] = p.scores[0].clone().with_landings(landings);
+            progress.update(&p);
+            termination.reached(&progress)
+        }).collect::<Vec<Option<Criterion>>>();
+
+        assert_eq!(vec![None, None, Some(Criterion::LandingStreak(3)), None], reasons);
+    }
+
+    #[test]
+    fn first_criterion_met_is_the_reason() {
+        let termination = Termination::new().with_max_generations(10).with_stagnation(2).with_landing_streak(1);
+        let mut progress = Progress::new();
+
+        let history = vec![(1.0, false), (2.0, false), (2.0, true), (2.0, true)];
+        let reasons = history.into_iter().enumerate().map(|(g, (score, landed))| {
+            progress.update(&generation(g as u32, score, landed));
+            termination.reached(&progress)
+        }).collect::<Vec<Option<Criterion>>>();
+
+        assert_eq!(vec![None, None, Some(Criterion::LandingStreak(1)), Some(Criterion::Stagnation(2))], reasons);
+    }
+}
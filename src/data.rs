@@ -10,7 +10,7 @@ use super::structure::{Program,Condition,Command,Expression,Sensor,Number};
 
 /// `SensorData` represents the information that is available for programs to decide what `ast::structure::Command`
 /// to execute when it is evaluated.
-#[derive(Copy,Clone,RustcEncodable)]
+#[derive(Copy,Clone,RustcEncodable,RustcDecodable)]
 pub struct SensorData {
     pub x:  Number,
     pub y:  Number,
@@ -19,6 +19,10 @@ pub struct SensorData {
     pub o:  Number,
     pub w:  Number,
     pub fuel: Number,
+    /// Height above the ground beneath the lander
+    pub altitude: Number,
+    /// Horizontal distance to the nearest landing pad, positive if it lies to the right
+    pub pad_distance: Number,
     pub hit_ground: bool,
     pub landed: bool,
     pub thrusting: bool,
@@ -40,6 +44,8 @@ impl SensorData {
             o:          0.0,
             w:          0.0,
             fuel:       1.0,
+            altitude:   0.0,
+            pad_distance: 0.0,
             hit_ground: false,
             landed:     false,
             thrusting:  false,
@@ -75,6 +81,14 @@ impl SensorData {
         SensorData { fuel: fuel, ..self }
     }
 
+    pub fn with_altitude(self, altitude: Number) -> SensorData {
+        SensorData { altitude: altitude, ..self }
+    }
+
+    pub fn with_pad_distance(self, pad_distance: Number) -> SensorData {
+        SensorData { pad_distance: pad_distance, ..self }
+    }
+
     pub fn hit_ground(self) -> SensorData {
         SensorData { hit_ground: true, ..self }
     }
@@ -108,6 +122,13 @@ impl Evaluate for Program {
 	}
 }
 
+/// A `Condition` on its own only controls the thruster: `Thrust` when it holds, `Skip` otherwise
+impl Evaluate for Condition {
+	fn evaluate(&self, sensor_data: SensorData) -> Command {
+		if self.value(sensor_data) { Command::Thrust } else { Command::Skip }
+	}
+}
+
 /// The numeric value of an `ast::structure::Expression`
 pub trait NumericValue {
 	fn value(&self, sensor_data: SensorData) -> Number;
@@ -136,6 +157,8 @@ impl NumericValue for Sensor {
             Sensor::O    => sensor_data.o,
             Sensor::W    => sensor_data.w,
             Sensor::Fuel => sensor_data.fuel,
+            Sensor::Altitude    => sensor_data.altitude,
+            Sensor::PadDistance => sensor_data.pad_distance,
         }
     }
 }
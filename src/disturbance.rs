@@ -0,0 +1,144 @@
+//! Random disturbances of the flight of the lander
+//!
+//! Without disturbances the physics is fully deterministic, and evolved controllers learn the
+//! exact dynamics by heart. `Disturbances` describes constant wind, gusts, noise on the thrust
+//! and random torque. Every frame a `Push` is drawn from them, which stays constant during the
+//! frame like the command does. The random numbers come from an RNG that the caller passes in,
+//! so an episode with the same seed is the same episode.
+
+extern crate rand;
+
+use self::rand::Rng;
+use self::rand::distributions::normal::StandardNormal;
+use super::structure::Number;
+
+/// Strength of the disturbances
+///
+/// All disturbances are accelerations. The default is no disturbance at all.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Disturbances {
+    /// Constant acceleration by the wind
+    pub wind_x: Number,
+    pub wind_y: Number,
+    /// Standard deviation of the acceleration by gusts, in either direction
+    pub gust_strength: Number,
+    /// Correlation time of the gusts, in units of time
+    pub gust_time: Number,
+    /// Standard deviation of the factor on the thrust, relative to 1
+    pub thrust_noise: Number,
+    /// Standard deviation of the random angular acceleration
+    pub torque: Number
+}
+
+impl Disturbances {
+    pub fn new() -> Disturbances {
+        Disturbances {
+            wind_x: 0.0,
+            wind_y: 0.0,
+            gust_strength: 0.0,
+            gust_time: 10.0,
+            thrust_noise: 0.0,
+            torque: 0.0
+        }
+    }
+
+    pub fn with_wind(self, wind_x: Number, wind_y: Number) -> Disturbances {
+        Disturbances { wind_x: wind_x, wind_y: wind_y, ..self }
+    }
+
+    pub fn with_gusts(self, gust_strength: Number, gust_time: Number) -> Disturbances {
+        Disturbances { gust_strength: gust_strength, gust_time: gust_time, ..self }
+    }
+
+    pub fn with_thrust_noise(self, thrust_noise: Number) -> Disturbances {
+        Disturbances { thrust_noise: thrust_noise, ..self }
+    }
+
+    pub fn with_torque(self, torque: Number) -> Disturbances {
+        Disturbances { torque: torque, ..self }
+    }
+
+    /// Whether these disturbances never push the lander
+    pub fn is_calm(&self) -> bool {
+        self.wind_x == 0.0 && self.wind_y == 0.0 && self.gust_strength == 0.0
+            && self.thrust_noise == 0.0 && self.torque == 0.0
+    }
+
+    /// Draw the push for a frame of dt units of time, moving the gust along
+    ///
+    /// The gust is an Ornstein-Uhlenbeck process, sampled exactly so that its statistics do not
+    /// depend on dt.
+    pub fn draw<R: Rng>(&self, gust: &mut Gust, dt: Number, rng: &mut R) -> Push {
+        if self.gust_strength > 0.0 {
+            let decay = if self.gust_time > 0.0 { (-dt / self.gust_time).exp() } else { 0.0 };
+            let spread = self.gust_strength * (1.0 - decay * decay).sqrt();
+            gust.x = gust.x * decay + spread * normal(rng);
+            gust.y = gust.y * decay + spread * normal(rng);
+        }
+
+        let thrust_factor = if self.thrust_noise > 0.0 { 1.0 + self.thrust_noise * normal(rng) } else { 1.0 };
+        Push {
+            ax: self.wind_x + gust.x,
+            ay: self.wind_y + gust.y,
+            thrust_factor: if thrust_factor > 0.0 { thrust_factor } else { 0.0 },
+            angular_acceleration: if self.torque > 0.0 { self.torque * normal(rng) } else { 0.0 }
+        }
+    }
+}
+
+/// The current acceleration by gusts, which carries over from frame to frame
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Gust {
+    pub x: Number,
+    pub y: Number
+}
+
+impl Gust {
+    /// No gust at all, the start of an episode
+    pub fn new() -> Gust {
+        Gust { x: 0.0, y: 0.0 }
+    }
+}
+
+/// The disturbance during a single frame
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Push {
+    pub ax: Number,
+    pub ay: Number,
+    pub thrust_factor: Number,
+    pub angular_acceleration: Number
+}
+
+impl Push {
+    /// A push that changes nothing
+    pub fn none() -> Push {
+        Push { ax: 0.0, ay: 0.0, thrust_factor: 1.0, angular_acceleration: 0.0 }
+    }
+}
+
+fn normal<R: Rng>(rng: &mut R) -> Number {
+    let StandardNormal(x) = rng.gen::<StandardNormal>();
+    x as Number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn gusts_wander_around_the_wind_with_the_given_strength() {
+        let disturbances = Disturbances::new().with_wind(1.0, 0.0).with_gusts(0.5, 5.0);
+        let mut rng = StdRng::from_seed(&[1, 2, 3]);
+        let mut gust = Gust::new();
+
+        let pushes = (0..20000).map(|_| disturbances.draw(&mut gust, 1.0, &mut rng)).collect::<Vec<Push>>();
+        let n = pushes.len() as Number;
+        let mean = pushes.iter().map(|p| p.ax).sum::<Number>() / n;
+        let sd = (pushes.iter().map(|p| (p.ax - mean) * (p.ax - mean)).sum::<Number>() / n).sqrt();
+
+        assert!((mean - 1.0).abs() < 0.1, "mean {}", mean);
+        assert!((sd - 0.5).abs() < 0.1, "sd {}", sd);
+        assert!(pushes.iter().all(|p| p.thrust_factor == 1.0 && p.angular_acceleration == 0.0));
+    }
+}
@@ -0,0 +1,92 @@
+//! Structural hashing of expression trees
+//!
+//! Trees that have the same structure hash the same. Constants are compared by their bits, except
+//! that all NaNs hash the same. `0.0` and `-0.0` hash differently, since dividing by them gives
+//! infinities of opposite sign. The hash is stable between runs, which makes it suitable as a key
+//! for caching the score of a program.
+
+use super::structure::{Program,Condition,Expression,Number};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+/// The structural hash of a tree
+pub fn structural_hash<T: Hash>(tree: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tree.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Bits of a constant, with a single NaN
+fn normalised_bits(x: Number) -> u32 {
+    if x.is_nan() {
+        ::std::f32::NAN.to_bits()
+    } else {
+        x.to_bits()
+    }
+}
+
+impl Hash for Program {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+		match *self {
+			Program::If(ref condition, ref left, ref right) => { condition.hash(state); left.hash(state); right.hash(state); },
+			Program::Command(ref command) => command.hash(state)
+		}
+    }
+}
+
+impl Hash for Condition {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+		match *self {
+			Condition::Not(ref condition)                => condition.hash(state),
+			Condition::Or(ref left, ref right)           => { left.hash(state); right.hash(state); },
+			Condition::And(ref left, ref right)          => { left.hash(state); right.hash(state); },
+			Condition::Less(ref left, ref right)         => { left.hash(state); right.hash(state); },
+			Condition::LessEqual(ref left, ref right)    => { left.hash(state); right.hash(state); },
+			Condition::Equal(ref left, ref right)        => { left.hash(state); right.hash(state); },
+			Condition::GreaterEqual(ref left, ref right) => { left.hash(state); right.hash(state); },
+			Condition::Greater(ref left, ref right)      => { left.hash(state); right.hash(state); },
+            _ => {}
+		}
+    }
+}
+
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+		match *self {
+			Expression::Constant(value)               => normalised_bits(value).hash(state),
+			Expression::Sensor(ref sensor)            => sensor.hash(state),
+			Expression::Plus(ref left, ref right)     => { left.hash(state); right.hash(state); },
+			Expression::Minus(ref left, ref right)    => { left.hash(state); right.hash(state); },
+			Expression::Multiply(ref left, ref right) => { left.hash(state); right.hash(state); },
+			Expression::Divide(ref left, ref right)   => { left.hash(state); right.hash(state); },
+		}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::*; }
+
+    use super::*;
+
+    #[test]
+    fn constants_are_normalised() {
+        assert!(structural_hash(&constant!(0.0)) != structural_hash(&constant!(-0.0)));
+        assert_eq!(structural_hash(&constant!(::std::f32::NAN)), structural_hash(&constant!(-::std::f32::NAN)));
+        assert!(structural_hash(&constant!(1.0)) != structural_hash(&constant!(1.5)));
+    }
+
+    #[test]
+    fn structure_is_hashed() {
+        let program = iff!(less!(y!(), constant!(2.0)), thrust!(), skip!());
+
+        assert_eq!(structural_hash(&program), structural_hash(&program.clone()));
+        assert!(structural_hash(&program) != structural_hash(&iff!(less!(y!(), constant!(2.0)), skip!(), thrust!())));
+        assert!(structural_hash(&program) != structural_hash(&iff!(greater!(y!(), constant!(2.0)), thrust!(), skip!())));
+    }
+}
@@ -0,0 +1,501 @@
+//! The `infix` module parses the notation produced by the `fmt::Display` implementations of
+//! `ast::structure`.
+//!
+//! Besides the fully parenthesized output of `Display`, it accepts hand written controllers that
+//! rely on operator precedence. From loosest to tightest binding:
+//!
+//! * `c then p else q`
+//! * `||`
+//! * `&&`
+//! * `!`
+//! * `<`, `<=`, `==`, `>=` and `>`
+//! * `+` and `-`
+//! * `*` and `/`
+//!
+//! Binary operators associate to the left, comparisons can not be chained.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate ast;
+//!
+//! use ast::structure::Program;
+//!
+//! fn main() {
+//!     let program: Program = "Vy < -0.5 && Fuel > 0 then Thrust else Skip".parse().unwrap();
+//!     assert_eq!("(((Vy < -0.5) && (Fuel > 0)) then Thrust else Skip)", program.to_string());
+//! }
+//! ```
+
+use std::str::FromStr;
+use super::structure::{Program,Condition,Command,Expression,Sensor,Number};
+use super::source::ParseError;
+
+/// Parse the infix representation of a `Program`
+pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+    let node = try!(parse(input));
+    to_program(&node)
+}
+
+/// Parse the infix representation of a `Condition`
+pub fn parse_condition(input: &str) -> Result<Condition, ParseError> {
+    let node = try!(parse(input));
+    to_condition(&node)
+}
+
+/// Parse the infix representation of an `Expression`
+pub fn parse_expression(input: &str) -> Result<Expression, ParseError> {
+    let node = try!(parse(input));
+    to_expression(&node)
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Program, ParseError> {
+        parse_program(s)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Condition, ParseError> {
+        parse_condition(s)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Expression, ParseError> {
+        parse_expression(s)
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Operator {
+    Or,
+    And,
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+}
+
+impl Operator {
+    fn precedence(&self) -> u8 {
+        match *self {
+            Operator::Or => 1,
+            Operator::And => 2,
+            // 3 is reserved for `!`
+            Operator::Less | Operator::LessEqual | Operator::Equal | Operator::GreaterEqual | Operator::Greater => 4,
+            Operator::Plus | Operator::Minus => 5,
+            Operator::Multiply | Operator::Divide => 6,
+        }
+    }
+
+    fn is_comparison(&self) -> bool {
+        self.precedence() == 4
+    }
+
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Operator::Or           => "||",
+            Operator::And          => "&&",
+            Operator::Less         => "<",
+            Operator::LessEqual    => "<=",
+            Operator::Equal        => "==",
+            Operator::GreaterEqual => ">=",
+            Operator::Greater      => ">",
+            Operator::Plus         => "+",
+            Operator::Minus        => "-",
+            Operator::Multiply     => "*",
+            Operator::Divide       => "/",
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+enum TokenKind<'a> {
+    LeftParen,
+    RightParen,
+    Not,
+    Operator(Operator),
+    Number(Number),
+    Name(&'a str),
+    End,
+}
+
+#[derive(Debug,Clone)]
+struct Token<'a> {
+    kind: TokenKind<'a>,
+    line: usize,
+    column: usize
+}
+
+fn error(line: usize, column: usize, message: String) -> ParseError {
+    ParseError { line: line, column: column, message: message }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    let (mut line, mut column) = (1, 1);
+
+    while let Some((start, c)) = chars.next() {
+        let (token_line, token_column) = (line, column);
+        let mut end = start + c.len_utf8();
+        column += 1;
+
+        let kind = match c {
+            '\n' => { line += 1; column = 1; continue; },
+            _ if c.is_whitespace() => continue,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            '+' => TokenKind::Operator(Operator::Plus),
+            '-' => TokenKind::Operator(Operator::Minus),
+            '*' => TokenKind::Operator(Operator::Multiply),
+            '/' => TokenKind::Operator(Operator::Divide),
+            '!' => TokenKind::Not,
+            '<' | '>' | '=' | '|' | '&' => {
+                let next = chars.peek().map(|&(_, n)| n);
+                let (operator, width) = match (c, next) {
+                    ('<', Some('=')) => (Operator::LessEqual, 2),
+                    ('>', Some('=')) => (Operator::GreaterEqual, 2),
+                    ('=', Some('=')) => (Operator::Equal, 2),
+                    ('|', Some('|')) => (Operator::Or, 2),
+                    ('&', Some('&')) => (Operator::And, 2),
+                    ('<', _)         => (Operator::Less, 1),
+                    ('>', _)         => (Operator::Greater, 1),
+                    _                => return Err(error(token_line, token_column, format!("unexpected character `{}`", c)))
+                };
+                if width == 2 {
+                    chars.next();
+                    column += 1;
+                }
+                TokenKind::Operator(operator)
+            },
+            _ if c.is_digit(10) || c == '.' => {
+                let mut previous = c;
+                while let Some(&(i, n)) = chars.peek() {
+                    let exponent_sign = (n == '-' || n == '+') && (previous == 'e' || previous == 'E');
+                    if !(n.is_digit(10) || n == '.' || n == 'e' || n == 'E' || exponent_sign) { break; }
+                    chars.next();
+                    column += 1;
+                    end = i + n.len_utf8();
+                    previous = n;
+                }
+                let literal = &input[start..end];
+                match literal.parse::<Number>() {
+                    Ok(value) => TokenKind::Number(value),
+                    Err(_)    => return Err(error(token_line, token_column, format!("invalid number `{}`", literal)))
+                }
+            },
+            _ if c.is_alphabetic() || c == '_' => {
+                while let Some(&(i, n)) = chars.peek() {
+                    if !(n.is_alphanumeric() || n == '_') { break; }
+                    chars.next();
+                    column += 1;
+                    end = i + n.len_utf8();
+                }
+                TokenKind::Name(&input[start..end])
+            },
+            _ => return Err(error(token_line, token_column, format!("unexpected character `{}`", c)))
+        };
+        tokens.push(Token { kind: kind, line: token_line, column: token_column });
+    }
+
+    tokens.push(Token { kind: TokenKind::End, line: line, column: column });
+    Ok(tokens)
+}
+
+/// Untyped syntax tree, checked against the structure in a second pass
+#[derive(Debug)]
+enum Tree<'a> {
+    Number(Number),
+    Name(&'a str),
+    Not(Box<Node<'a>>),
+    Binary(Operator, Box<Node<'a>>, Box<Node<'a>>),
+    If(Box<Node<'a>>, Box<Node<'a>>, Box<Node<'a>>),
+}
+
+#[derive(Debug)]
+struct Node<'a> {
+    tree: Tree<'a>,
+    line: usize,
+    column: usize
+}
+
+impl <'a> Node<'a> {
+    fn describe(&self) -> String {
+        match self.tree {
+            Tree::Number(value)            => format!("number `{}`", value),
+            Tree::Name(name)               => format!("`{}`", name),
+            Tree::Not(_)                   => "`!`".to_string(),
+            Tree::Binary(operator, _, _)   => format!("`{}`", operator.symbol()),
+            Tree::If(_, _, _)              => "`then`".to_string(),
+        }
+    }
+
+    fn expected(&self, what: &str) -> ParseError {
+        error(self.line, self.column, format!("expected {}, found {}", what, self.describe()))
+    }
+}
+
+fn parse(input: &str) -> Result<Node, ParseError> {
+    let tokens = try!(tokenize(input));
+    let mut parser = Parser { tokens: tokens, position: 0 };
+    let node = try!(parser.program());
+    match parser.peek().kind {
+        TokenKind::End => Ok(node),
+        _              => Err(parser.unexpected("end of input"))
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize
+}
+
+impl <'a> Parser<'a> {
+    fn peek(&self) -> &Token<'a> {
+        &self.tokens[self.position]
+    }
+
+    fn next(&mut self) -> Token<'a> {
+        let token = self.tokens[self.position].clone();
+        if token.kind != TokenKind::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let token = self.peek();
+        let found = match token.kind {
+            TokenKind::LeftParen          => "`(`".to_string(),
+            TokenKind::RightParen         => "`)`".to_string(),
+            TokenKind::Not                => "`!`".to_string(),
+            TokenKind::Operator(operator) => format!("`{}`", operator.symbol()),
+            TokenKind::Number(value)      => format!("number `{}`", value),
+            TokenKind::Name(name)         => format!("`{}`", name),
+            TokenKind::End                => "end of input".to_string(),
+        };
+        error(token.line, token.column, format!("expected {}, found {}", expected, found))
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        self.peek().kind == TokenKind::Name(name)
+    }
+
+    /// `binary ['then' program 'else' program]`
+    fn program(&mut self) -> Result<Node<'a>, ParseError> {
+        let condition = try!(self.binary(1));
+        if !self.is_name("then") {
+            return Ok(condition);
+        }
+
+        let then = self.next();
+        let left = try!(self.program());
+        if !self.is_name("else") {
+            return Err(self.unexpected("`else`"));
+        }
+        self.next();
+        let right = try!(self.program());
+        Ok(Node { tree: Tree::If(Box::new(condition), Box::new(left), Box::new(right)), line: then.line, column: then.column })
+    }
+
+    /// Precedence climbing over the binary operators
+    fn binary(&mut self, min_precedence: u8) -> Result<Node<'a>, ParseError> {
+        let mut left = try!(self.unary());
+        loop {
+            let operator = match self.peek().kind {
+                TokenKind::Operator(operator) if operator.precedence() >= min_precedence => operator,
+                _ => return Ok(left)
+            };
+            let token = self.next();
+            let right = try!(self.binary(operator.precedence() + 1));
+            left = Node { tree: Tree::Binary(operator, Box::new(left), Box::new(right)), line: token.line, column: token.column };
+
+            if operator.is_comparison() {
+                if let TokenKind::Operator(next) = self.peek().kind {
+                    if next.is_comparison() {
+                        let token = self.peek();
+                        return Err(error(token.line, token.column, "comparison operators can not be chained".to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node<'a>, ParseError> {
+        let token = self.peek().clone();
+        match token.kind {
+            TokenKind::Not => {
+                self.next();
+                let operand = try!(self.binary(3));
+                Ok(Node { tree: Tree::Not(Box::new(operand)), line: token.line, column: token.column })
+            },
+            TokenKind::Operator(Operator::Minus) => {
+                self.next();
+                // Only negative literals, `Expression` has no negation
+                let value = match self.peek().kind {
+                    TokenKind::Number(value) => value,
+                    TokenKind::Name("inf")   => Number::INFINITY,
+                    _                        => return Err(self.unexpected("a number after unary `-`"))
+                };
+                self.next();
+                Ok(Node { tree: Tree::Number(-value), line: token.line, column: token.column })
+            },
+            TokenKind::Number(value) => {
+                self.next();
+                Ok(Node { tree: Tree::Number(value), line: token.line, column: token.column })
+            },
+            TokenKind::Name(name) if name != "then" && name != "else" => {
+                self.next();
+                Ok(Node { tree: Tree::Name(name), line: token.line, column: token.column })
+            },
+            TokenKind::LeftParen => {
+                self.next();
+                let inner = try!(self.program());
+                match self.peek().kind {
+                    TokenKind::RightParen => { self.next(); Ok(inner) },
+                    _                     => Err(self.unexpected("`)`"))
+                }
+            },
+            _ => Err(self.unexpected("an operand"))
+        }
+    }
+}
+
+fn to_program(node: &Node) -> Result<Program, ParseError> {
+    match node.tree {
+        Tree::If(ref condition, ref left, ref right) =>
+            Ok(Program::If(Box::new(try!(to_condition(condition))), Box::new(try!(to_program(left))), Box::new(try!(to_program(right))))),
+        Tree::Name(name) => {
+            let command = match name {
+                "Skip"   => Command::Skip,
+                "Left"   => Command::Left,
+                "Right"  => Command::Right,
+                "Thrust" => Command::Thrust,
+                _        => return Err(node.expected("a program"))
+            };
+            Ok(Program::Command(Box::new(command)))
+        },
+        _ => Err(node.expected("a program"))
+    }
+}
+
+fn to_condition(node: &Node) -> Result<Condition, ParseError> {
+    match node.tree {
+        Tree::Name("True")  => Ok(Condition::True),
+        Tree::Name("False") => Ok(Condition::False),
+        Tree::Not(ref inner) => Ok(Condition::Not(Box::new(try!(to_condition(inner))))),
+        Tree::Binary(Operator::Or, ref left, ref right) =>
+            Ok(Condition::Or(Box::new(try!(to_condition(left))), Box::new(try!(to_condition(right))))),
+        Tree::Binary(Operator::And, ref left, ref right) =>
+            Ok(Condition::And(Box::new(try!(to_condition(left))), Box::new(try!(to_condition(right))))),
+        Tree::Binary(operator, ref left, ref right) if operator.is_comparison() => {
+            let l = Box::new(try!(to_expression(left)));
+            let r = Box::new(try!(to_expression(right)));
+            Ok(match operator {
+                Operator::Less         => Condition::Less(l, r),
+                Operator::LessEqual    => Condition::LessEqual(l, r),
+                Operator::Equal        => Condition::Equal(l, r),
+                Operator::GreaterEqual => Condition::GreaterEqual(l, r),
+                _                      => Condition::Greater(l, r),
+            })
+        },
+        _ => Err(node.expected("a condition"))
+    }
+}
+
+fn to_expression(node: &Node) -> Result<Expression, ParseError> {
+    match node.tree {
+        Tree::Number(value) => Ok(Expression::Constant(value)),
+        Tree::Name("NaN")   => Ok(Expression::Constant(Number::NAN)),
+        Tree::Name("inf")   => Ok(Expression::Constant(Number::INFINITY)),
+        Tree::Name(name) => {
+            let sensor = match name {
+                "X"    => Sensor::X,
+                "Y"    => Sensor::Y,
+                "Vx"   => Sensor::Vx,
+                "Vy"   => Sensor::Vy,
+                "O"    => Sensor::O,
+                "W"    => Sensor::W,
+                "Fuel" => Sensor::Fuel,
+                "Altitude"    => Sensor::Altitude,
+                "PadDistance" => Sensor::PadDistance,
+                _      => return Err(node.expected("an expression"))
+            };
+            Ok(Expression::Sensor(Box::new(sensor)))
+        },
+        Tree::Binary(operator, ref left, ref right) if operator.precedence() >= 5 => {
+            let l = Box::new(try!(to_expression(left)));
+            let r = Box::new(try!(to_expression(right)));
+            Ok(match operator {
+                Operator::Plus     => Expression::Plus(l, r),
+                Operator::Minus    => Expression::Minus(l, r),
+                Operator::Multiply => Expression::Multiply(l, r),
+                _                  => Expression::Divide(l, r),
+            })
+        },
+        _ => Err(node.expected("an expression"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::*; }
+
+    use super::*;
+    use structure::Program;
+    use rand;
+    use rand::Rng;
+
+    #[test]
+    fn should_parse_displayed_programs() {
+        assert_eq!(Ok(iff!(less!(vy!(), constant!(0.5)), thrust!(), skip!())),
+                   parse_program("((Vy < 0.5) then Thrust else Skip)"));
+        assert_eq!(Ok(not!(or!(T!(), F!()))), parse_condition("!(True || False)"));
+        assert_eq!(Ok(divide!(fuel!(), constant!(-2.0))), parse_expression("(Fuel / -2)"));
+    }
+
+    #[test]
+    fn should_respect_operator_precedence() {
+        assert_eq!(Ok(plus!(y!(), multiply!(vy!(), constant!(2.0)))), parse_expression("Y + Vy * 2"));
+        assert_eq!(Ok(minus!(minus!(y!(), vy!()), fuel!())), parse_expression("Y - Vy - Fuel"));
+        assert_eq!(Ok(or!(and!(T!(), F!()), not!(less!(y!(), plus!(vy!(), constant!(1.0)))))),
+                   parse_condition("True && False || !Y < Vy + 1"));
+        assert_eq!(Ok(iff!(T!(), iff!(F!(), left!(), right!()), skip!())),
+                   parse_program("True then False then Left else Right else Skip"));
+    }
+
+    #[test]
+    fn should_report_line_and_column_of_parse_errors() {
+        let error = parse_program("(Vy < 0.5)\n  then Jump else Skip").unwrap_err();
+        assert_eq!((2, 8), (error.line, error.column));
+
+        let error = parse_condition("Vy < 1 < 2").unwrap_err();
+        assert_eq!((1, 8), (error.line, error.column));
+
+        let error = parse_expression("Vy + (Y").unwrap_err();
+        assert_eq!((1, 8), (error.line, error.column));
+    }
+
+    #[test]
+    fn parsing_displayed_random_programs_should_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let program = rng.gen::<Program>();
+            assert_eq!(Ok(program.clone()), program.to_string().parse::<Program>());
+        }
+    }
+}
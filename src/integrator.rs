@@ -0,0 +1,145 @@
+//! Numerical integration of the motion of the lander
+//!
+//! The lander is a point with an orientation. During a step the thrust and the angular
+//! acceleration are held constant, so the only thing that changes the acceleration within a step
+//! is the orientation. All constants are per unit of time, which makes a trajectory (nearly)
+//! independent of the step size for the higher order integrators.
+
+use super::structure::Number;
+use super::data::SensorData;
+
+/// Method of advancing the motion by one step
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub enum Integrator {
+    /// Update the velocities first and move with the new velocities
+    ///
+    /// With a step of 1 this is exactly the original frame update.
+    SemiImplicitEuler,
+    /// Velocity Verlet, exact for a constant acceleration
+    Verlet,
+    /// Classic fourth order Runge-Kutta
+    RungeKutta4
+}
+
+/// Accelerations that stay constant during a step
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Forces {
+    /// Acceleration along the axis of the lander
+    pub thrust: Number,
+    /// Vertical acceleration, negative is down
+    pub gravity: Number,
+    /// Acceleration from outside, like wind
+    pub push_x: Number,
+    pub push_y: Number,
+    pub angular_acceleration: Number
+}
+
+/// The part of the state that changes with the motion
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Motion {
+    pub x: Number,
+    pub y: Number,
+    pub vx: Number,
+    pub vy: Number,
+    pub o: Number,
+    pub w: Number
+}
+
+impl Motion {
+    pub fn of(sensor_data: &SensorData) -> Motion {
+        Motion {
+            x: sensor_data.x, y: sensor_data.y,
+            vx: sensor_data.vx, vy: sensor_data.vy,
+            o: sensor_data.o, w: sensor_data.w
+        }
+    }
+
+    pub fn apply(&self, sensor_data: &mut SensorData) {
+        sensor_data.x = self.x;
+        sensor_data.y = self.y;
+        sensor_data.vx = self.vx;
+        sensor_data.vy = self.vy;
+        sensor_data.o = self.o;
+        sensor_data.w = self.w;
+    }
+
+    /// Time derivative of the motion
+    fn derivative(&self, forces: &Forces) -> Motion {
+        let (ax, ay) = forces.acceleration(self.o);
+        Motion { x: self.vx, y: self.vy, vx: ax, vy: ay, o: self.w, w: forces.angular_acceleration }
+    }
+
+    /// self + h * d
+    fn plus(&self, d: &Motion, h: Number) -> Motion {
+        Motion {
+            x: self.x + h * d.x, y: self.y + h * d.y,
+            vx: self.vx + h * d.vx, vy: self.vy + h * d.vy,
+            o: self.o + h * d.o, w: self.w + h * d.w
+        }
+    }
+}
+
+impl Forces {
+    /// Linear acceleration at orientation o
+    pub fn acceleration(&self, o: Number) -> (Number, Number) {
+        (-self.thrust * o.sin() + self.push_x, self.thrust * o.cos() + self.gravity + self.push_y)
+    }
+}
+
+impl Integrator {
+    /// Advance the motion by a step of h units of time
+    pub fn step(&self, m: Motion, forces: &Forces, h: Number) -> Motion {
+        match *self {
+            Integrator::SemiImplicitEuler => {
+                let w = m.w + forces.angular_acceleration * h;
+                let o = m.o + w * h;
+                let (ax, ay) = forces.acceleration(o);
+                let vx = m.vx + ax * h;
+                let vy = m.vy + ay * h;
+                Motion { x: m.x + vx * h, y: m.y + vy * h, vx: vx, vy: vy, o: o, w: w }
+            },
+            Integrator::Verlet => {
+                let alpha = forces.angular_acceleration;
+                let o = m.o + m.w * h + 0.5 * alpha * h * h;
+                let (ax, ay) = forces.acceleration(m.o);
+                let (bx, by) = forces.acceleration(o);
+                Motion {
+                    x: m.x + m.vx * h + 0.5 * ax * h * h,
+                    y: m.y + m.vy * h + 0.5 * ay * h * h,
+                    vx: m.vx + 0.5 * (ax + bx) * h,
+                    vy: m.vy + 0.5 * (ay + by) * h,
+                    o: o,
+                    w: m.w + alpha * h
+                }
+            },
+            Integrator::RungeKutta4 => {
+                let k1 = m.derivative(forces);
+                let k2 = m.plus(&k1, h / 2.0).derivative(forces);
+                let k3 = m.plus(&k2, h / 2.0).derivative(forces);
+                let k4 = m.plus(&k3, h).derivative(forces);
+                m.plus(&k1, h / 6.0).plus(&k2, h / 3.0).plus(&k3, h / 3.0).plus(&k4, h / 6.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn higher_order_integrators_do_not_depend_on_the_step_size() {
+        let start = Motion { x: 0.0, y: 100.0, vx: 1.0, vy: 2.0, o: 0.3, w: 0.0 };
+        let forces = Forces { thrust: 0.6, gravity: -0.5, push_x: 0.1, push_y: 0.0, angular_acceleration: 0.0 };
+        let (ax, ay) = forces.acceleration(0.3);
+        let t = 10.0;
+
+        for integrator in &[Integrator::Verlet, Integrator::RungeKutta4] {
+            for &steps in &[1, 10, 100] {
+                let m = (0..steps).fold(start, |m, _| integrator.step(m, &forces, t / steps as Number));
+                assert!((m.x - (t + 0.5 * ax * t * t)).abs() < 1e-3, "{:?}/{} x {}", integrator, steps, m.x);
+                assert!((m.y - (100.0 + 2.0 * t + 0.5 * ay * t * t)).abs() < 1e-3, "{:?}/{} y {}", integrator, steps, m.y);
+            }
+        }
+    }
+}
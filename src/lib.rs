@@ -23,15 +23,23 @@ pub mod pick;
 #[macro_use]
 pub mod macros;
 
+pub mod constants;
 pub mod copy;
 pub mod darwin;
 pub mod data;
 pub mod depth;
+pub mod disturbance;
+pub mod hash;
+pub mod infix;
+pub mod integrator;
 pub mod random;
 pub mod serialize;
 pub mod simplify;
 pub mod simulation;
+pub mod size;
 pub mod source;
 pub mod structure;
+pub mod terrain;
 pub mod visit;
 pub mod num;
+pub mod observation;
@@ -73,6 +73,16 @@ macro_rules! fuel {
     () => (ast::structure::Expression::Sensor(Box::new(ast::structure::Sensor::Fuel)))
 }
 
+#[macro_export]
+macro_rules! altitude {
+    () => (ast::structure::Expression::Sensor(Box::new(ast::structure::Sensor::Altitude)))
+}
+
+#[macro_export]
+macro_rules! pad_distance {
+    () => (ast::structure::Expression::Sensor(Box::new(ast::structure::Sensor::PadDistance)))
+}
+
 /*
 #[macro_export]
 macro_rules! x {
@@ -0,0 +1,202 @@
+//! What a program gets to see of the state of the lander
+//!
+//! Real sensors are noisy, have a limited resolution and report late. An `Observation` describes
+//! this for every `ast::structure::Sensor`, and an `Observer` applies it during an episode. The
+//! true state still drives the physics; only the program sees the observed state.
+
+extern crate rand;
+
+use std::collections::VecDeque;
+use self::rand::Rng;
+use self::rand::distributions::normal::StandardNormal;
+use super::data::{SensorData, NumericValue};
+use super::structure::{Sensor, Number};
+
+/// How a single sensor reads its value
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct SensorModel {
+    /// Standard deviation of the Gaussian noise added to the value
+    pub noise: Number,
+    /// Resolution to which the value is rounded, 0 for none
+    pub quantum: Number,
+    /// Number of frames the value lags behind
+    pub delay: u32
+}
+
+impl SensorModel {
+    /// A sensor that reads the exact value
+    pub fn exact() -> SensorModel {
+        SensorModel { noise: 0.0, quantum: 0.0, delay: 0 }
+    }
+
+    pub fn with_noise(self, noise: Number) -> SensorModel {
+        SensorModel { noise: noise, ..self }
+    }
+
+    pub fn with_quantum(self, quantum: Number) -> SensorModel {
+        SensorModel { quantum: quantum, ..self }
+    }
+
+    pub fn with_delay(self, delay: u32) -> SensorModel {
+        SensorModel { delay: delay, ..self }
+    }
+
+    /// Read a (delayed) true value
+    pub fn read<R: Rng>(&self, value: Number, rng: &mut R) -> Number {
+        let noisy = if self.noise > 0.0 {
+            let StandardNormal(n) = rng.gen::<StandardNormal>();
+            value + self.noise * n as Number
+        } else {
+            value
+        };
+        if self.quantum > 0.0 { (noisy / self.quantum).round() * self.quantum } else { noisy }
+    }
+}
+
+/// The sensor models of all sensors
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Observation {
+    pub x: SensorModel,
+    pub y: SensorModel,
+    pub vx: SensorModel,
+    pub vy: SensorModel,
+    pub o: SensorModel,
+    pub w: SensorModel,
+    pub fuel: SensorModel,
+    pub altitude: SensorModel,
+    pub pad_distance: SensorModel
+}
+
+const SENSORS: [Sensor; 9] = [
+    Sensor::X, Sensor::Y, Sensor::Vx, Sensor::Vy, Sensor::O, Sensor::W,
+    Sensor::Fuel, Sensor::Altitude, Sensor::PadDistance
+];
+
+impl Observation {
+    /// All sensors read the exact value
+    pub fn new() -> Observation {
+        let exact = SensorModel::exact();
+        Observation {
+            x: exact, y: exact, vx: exact, vy: exact, o: exact, w: exact,
+            fuel: exact, altitude: exact, pad_distance: exact
+        }
+    }
+
+    /// Use the model for a single sensor
+    pub fn with(self, sensor: Sensor, model: SensorModel) -> Observation {
+        let mut observation = self;
+        *observation.model_mut(sensor) = model;
+        observation
+    }
+
+    /// Use the model for every sensor
+    pub fn with_all(self, model: SensorModel) -> Observation {
+        SENSORS.iter().fold(self, |observation, &sensor| observation.with(sensor, model))
+    }
+
+    pub fn model(&self, sensor: Sensor) -> &SensorModel {
+        match sensor {
+            Sensor::X           => &self.x,
+            Sensor::Y           => &self.y,
+            Sensor::Vx          => &self.vx,
+            Sensor::Vy          => &self.vy,
+            Sensor::O           => &self.o,
+            Sensor::W           => &self.w,
+            Sensor::Fuel        => &self.fuel,
+            Sensor::Altitude    => &self.altitude,
+            Sensor::PadDistance => &self.pad_distance
+        }
+    }
+
+    fn model_mut(&mut self, sensor: Sensor) -> &mut SensorModel {
+        match sensor {
+            Sensor::X           => &mut self.x,
+            Sensor::Y           => &mut self.y,
+            Sensor::Vx          => &mut self.vx,
+            Sensor::Vy          => &mut self.vy,
+            Sensor::O           => &mut self.o,
+            Sensor::W           => &mut self.w,
+            Sensor::Fuel        => &mut self.fuel,
+            Sensor::Altitude    => &mut self.altitude,
+            Sensor::PadDistance => &mut self.pad_distance
+        }
+    }
+
+    /// Whether every sensor reads the exact value
+    pub fn is_exact(&self) -> bool {
+        SENSORS.iter().all(|&sensor| *self.model(sensor) == SensorModel::exact())
+    }
+
+    /// The longest delay of any sensor
+    pub fn max_delay(&self) -> u32 {
+        SENSORS.iter().map(|&sensor| self.model(sensor).delay).max().unwrap_or(0)
+    }
+}
+
+/// Applies an `Observation` to the states of a single episode
+pub struct Observer {
+    observation: Observation,
+    /// The most recent true states, newest first
+    history: VecDeque<SensorData>
+}
+
+impl Observer {
+    pub fn new(observation: Observation) -> Observer {
+        Observer { observation: observation, history: VecDeque::new() }
+    }
+
+    /// What the program sees of the current true state
+    ///
+    /// Should be called once per frame. Delayed sensors read the oldest state seen so far until
+    /// enough frames have passed. Everything that is not a sensor, like `landed`, is taken from
+    /// the current state.
+    pub fn observe<R: Rng>(&mut self, state: &SensorData, rng: &mut R) -> SensorData {
+        self.history.push_front(*state);
+        self.history.truncate(self.observation.max_delay() as usize + 1);
+
+        let mut observed = *state;
+        for &sensor in SENSORS.iter() {
+            let model = self.observation.model(sensor);
+            let past = self.history.get(model.delay as usize).or(self.history.back()).unwrap_or(state);
+            set(&mut observed, sensor, model.read(sensor.value(*past), rng));
+        }
+        observed
+    }
+}
+
+fn set(sensor_data: &mut SensorData, sensor: Sensor, value: Number) {
+    match sensor {
+        Sensor::X           => sensor_data.x = value,
+        Sensor::Y           => sensor_data.y = value,
+        Sensor::Vx          => sensor_data.vx = value,
+        Sensor::Vy          => sensor_data.vy = value,
+        Sensor::O           => sensor_data.o = value,
+        Sensor::W           => sensor_data.w = value,
+        Sensor::Fuel        => sensor_data.fuel = value,
+        Sensor::Altitude    => sensor_data.altitude = value,
+        Sensor::PadDistance => sensor_data.pad_distance = value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn sensors_are_delayed_and_quantised() {
+        let observation = Observation::new()
+            .with(Sensor::Y, SensorModel::exact().with_delay(2))
+            .with(Sensor::Vy, SensorModel::exact().with_quantum(0.5));
+        let mut observer = Observer::new(observation);
+        let mut rng = StdRng::from_seed(&[1]);
+
+        let ys = (0..5).map(|i| {
+            let observed = observer.observe(&SensorData::new().with_y(i as Number).with_vy(-0.7).with_x(3.0), &mut rng);
+            assert_eq!((3.0, -0.5), (observed.x, observed.vy));
+            observed.y
+        }).collect::<Vec<Number>>();
+
+        assert_eq!(vec![0.0, 0.0, 0.0, 1.0, 2.0], ys);
+    }
+}
@@ -4,14 +4,20 @@ extern crate rand;
 
 /// The `pick` macro allows to randomly select one expression from a sequence of
 /// expressions according to associated weights.
+///
+/// The first argument is the `rand::Rng` to draw from, so that runs seeded with the same value
+/// make the same choices. Only the selected expression is evaluated.
+///
+/// ```ignore
+/// let command = pick![rng; 3, Command::Thrust, 1, Command::Skip];
+/// ```
 #[macro_export]
 macro_rules! pick {
-    ($( $weight: expr, $expression: expr),+) => {{
+    ($rng: expr; $( $weight: expr, $expression: expr),+) => {{
         let total = 0 $(+ $weight)+;
         let mut bound = 0;
 
-        let mut rng = rand::thread_rng();
-        let random_number = rng.gen_range(0, total);
+        let random_number = $rng.gen_range(0, total);
         let result = $( if bound <= random_number && random_number < { bound += $weight; bound } {
             $expression
         } else )+ {
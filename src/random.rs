@@ -4,6 +4,13 @@
 //! making use of a macro `pick` that can choose an expression according to
 //! weight.
 //!
+//! The weights of the alternatives are kept in `GrammarWeights`, so they can
+//! be changed per experiment through `Weighted`.
+//!
+//! The `Rand` implementations give little control over the size of the trees.
+//! For that there is a `Generator`, which creates trees between a minimum and
+//! a maximum depth with the grow, full or ramped half-and-half method.
+//!
 //! # Examples
 //!
 //! ```
@@ -27,69 +34,559 @@ extern crate rand;
 
 pub use self::rand::Rng;
 
+use std::cmp::{max, min};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use rustc_serialize::json;
 use super::structure::{Program,Condition,Command,Expression,Sensor};
 
+/// Relative weights of the alternatives of every node type in the grammar
+///
+/// Random trees, both from `rand::Rand` and from a `Generator`, and the mutation operators in
+/// `ast::darwin::mutation` draw their nodes according to these weights. A weight of 0 switches an
+/// alternative off, e.g. a sensor that should not be available in an experiment. Every group
+/// needs at least one alternative with a positive weight.
+///
+/// The weights can be read from JSON, see `resources/weights.json` for the defaults.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct GrammarWeights {
+    pub program: ProgramWeights,
+    pub condition: ConditionWeights,
+    pub expression: ExpressionWeights,
+    pub sensor: SensorWeights,
+    pub command: CommandWeights,
+    pub mutation: MutationWeights
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct ProgramWeights {
+    /// Needs to be lower than `command`, otherwise generating a tree with `rand::Rand` is
+    /// unlikely to terminate.
+    pub iff: u32,
+    pub command: u32
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct ConditionWeights {
+    pub true_literal: u32,
+    pub false_literal: u32,
+    pub not: u32,
+    pub or: u32,
+    pub and: u32,
+    pub less: u32,
+    pub less_equal: u32,
+    pub equal: u32,
+    pub greater_equal: u32,
+    pub greater: u32
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct ExpressionWeights {
+    pub constant: u32,
+    pub sensor: u32,
+    pub plus: u32,
+    pub minus: u32,
+    pub multiply: u32,
+    pub divide: u32
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct SensorWeights {
+    pub x: u32,
+    pub y: u32,
+    pub vx: u32,
+    pub vy: u32,
+    pub o: u32,
+    pub w: u32,
+    pub fuel: u32,
+    /// The terrain sensors tell nothing new on `Terrain::everywhere`, so they are 0 by default
+    pub altitude: u32,
+    pub pad_distance: u32
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct CommandWeights {
+    pub skip: u32,
+    pub left: u32,
+    pub right: u32,
+    pub thrust: u32
+}
+
+/// Weights of the choices made by `ast::darwin::mutation`
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct MutationWeights {
+    /// Replace a condition or expression by a new random one
+    pub replace_subtree: u32,
+    /// Replace the operator of a condition or expression, keeping its arguments
+    pub controlled: u32,
+    /// Keep the arguments in place in a controlled mutation
+    pub keep_arms: u32,
+    /// Swap the arguments in a controlled mutation
+    pub swap_arms: u32,
+    /// Replace a constant by a new random number
+    pub new_constant: u32,
+    /// Multiply a constant by a random factor
+    pub scale_constant: u32,
+    /// Add a random offset to a constant
+    pub shift_constant: u32
+}
+
+impl GrammarWeights {
+    /// The default weights, which are also used by the `rand::Rand` implementations
+    pub fn new() -> GrammarWeights {
+        GrammarWeights {
+            program: ProgramWeights { iff: 3, command: 4 },
+            condition: ConditionWeights {
+                true_literal: 10, false_literal: 10,
+                not: 3, or: 2, and: 2,
+                less: 1, less_equal: 1, equal: 2, greater_equal: 1, greater: 1
+            },
+            expression: ExpressionWeights { constant: 5, sensor: 5, plus: 1, minus: 1, multiply: 1, divide: 1 },
+            sensor: SensorWeights { x: 1, y: 1, vx: 1, vy: 1, o: 1, w: 1, fuel: 1, altitude: 0, pad_distance: 0 },
+            command: CommandWeights { skip: 1, left: 1, right: 1, thrust: 1 },
+            mutation: MutationWeights {
+                replace_subtree: 1, controlled: 4,
+                keep_arms: 8, swap_arms: 2,
+                new_constant: 1, scale_constant: 7, shift_constant: 2
+            }
+        }
+    }
+
+    /// Read weights from JSON
+    pub fn load(r: &mut Read) -> Result<GrammarWeights, Box<Error>> {
+        let mut content = String::new();
+        try!(r.read_to_string(&mut content));
+        let weights: GrammarWeights = try!(json::decode(&content));
+        try!(weights.validate());
+        Ok(weights)
+    }
+
+    pub fn load_file(path: &Path) -> Result<GrammarWeights, Box<Error>> {
+        let mut f = try!(File::open(path));
+        GrammarWeights::load(&mut f)
+    }
+
+    /// Check that every group has at least one alternative to pick
+    pub fn validate(&self) -> Result<(), String> {
+        let c = &self.condition;
+        let e = &self.expression;
+        let s = &self.sensor;
+        let m = &self.mutation;
+        let groups = [
+            ("program", self.program.iff + self.program.command),
+            ("condition", c.true_literal + c.false_literal + c.not + c.or + c.and + c.less + c.less_equal + c.equal + c.greater_equal + c.greater),
+            ("expression", e.constant + e.sensor + e.plus + e.minus + e.multiply + e.divide),
+            ("sensor", s.x + s.y + s.vx + s.vy + s.o + s.w + s.fuel + s.altitude + s.pad_distance),
+            ("command", self.command.skip + self.command.left + self.command.right + self.command.thrust),
+            ("mutation subtree", m.replace_subtree + m.controlled),
+            ("mutation arms", m.keep_arms + m.swap_arms),
+            ("mutation constant", m.new_constant + m.scale_constant + m.shift_constant),
+        ];
+        match groups.iter().find(|&&(_, total)| total == 0) {
+            Some(&(name, _)) => Err(format!("all {} weights are 0", name)),
+            None             => Ok(())
+        }
+    }
+}
+
+/// Trees that can be drawn according to `GrammarWeights`
+pub trait Weighted: Sized {
+    fn weighted<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> Self;
+}
+
+impl Weighted for Program {
+    fn weighted<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> Self {
+        let w = &weights.program;
+        pick![rng;
+            w.iff, Program::If(Box::new(Condition::weighted(weights, rng)), Box::new(Program::weighted(weights, rng)), Box::new(Program::weighted(weights, rng))),
+            w.command, Program::Command(Box::new(Command::weighted(weights, rng)))
+        ]
+    }
+}
+
+impl Weighted for Condition {
+    fn weighted<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> Self {
+        let w = &weights.condition;
+        pick![rng;
+            w.true_literal,  Condition::True,
+            w.false_literal, Condition::False,
+
+            w.not, Condition::Not(Box::new(Condition::weighted(weights, rng))),
+            w.or,  Condition::Or(Box::new(Condition::weighted(weights, rng)), Box::new(Condition::weighted(weights, rng))),
+            w.and, Condition::And(Box::new(Condition::weighted(weights, rng)), Box::new(Condition::weighted(weights, rng))),
+
+            w.less,          Condition::Less(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.less_equal,    Condition::LessEqual(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.greater,       Condition::Greater(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.greater_equal, Condition::GreaterEqual(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.equal,         Condition::Equal(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng)))
+        ]
+    }
+}
+
+impl Weighted for Command {
+    fn weighted<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> Self {
+        let w = &weights.command;
+        pick![rng; w.skip, Command::Skip, w.left, Command::Left, w.right, Command::Right, w.thrust, Command::Thrust]
+    }
+}
+
+impl Weighted for Expression {
+    fn weighted<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> Self {
+        let w = &weights.expression;
+        pick![rng;
+            w.constant, Expression::Constant(rng.next_f32()),
+            w.sensor,   Expression::Sensor(Box::new(Sensor::weighted(weights, rng))),
+            w.plus,     Expression::Plus(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.minus,    Expression::Minus(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.multiply, Expression::Multiply(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng))),
+            w.divide,   Expression::Divide(Box::new(Expression::weighted(weights, rng)), Box::new(Expression::weighted(weights, rng)))
+        ]
+    }
+}
+
+impl Weighted for Sensor {
+    fn weighted<R: rand::Rng>(weights: &GrammarWeights, rng: &mut R) -> Self {
+        let w = &weights.sensor;
+        pick![rng;
+            w.y,    Sensor::Y,
+            w.vy,   Sensor::Vy,
+            w.fuel, Sensor::Fuel,
+            w.x,    Sensor::X,
+            w.vx,   Sensor::Vx,
+            w.o,    Sensor::O,
+            w.w,    Sensor::W,
+            w.altitude,     Sensor::Altitude,
+            w.pad_distance, Sensor::PadDistance
+        ]
+    }
+}
+
 impl rand::Rand for Program {
     fn rand<R: rand::Rng>(rng: &mut R) -> Self {
-        pick![
-            3, Program::If(Box::new(Condition::rand(rng)), Box::new(Program::rand(rng)), Box::new(Program::rand(rng))),
-            // This weight needs to be SLIGHTLY higher than the previous one, to increase the
-            // chances of termination during random generation. Otherwise there's a too high chance
-            // we're going to be generating Programs that contain Programs that contain Programs,
-            // etc.
-            4, Program::Command(Box::new(Command::rand(rng)))
-        ]
+        Program::weighted(&GrammarWeights::new(), rng)
     }
 }
 
 impl rand::Rand for Condition {
     fn rand<R: rand::Rng>(rng: &mut R) -> Self {
-        pick![
-            10, Condition::True,
-            10, Condition::False,
-
-            3,  Condition::Not(Box::new(Condition::rand(rng))),
-            2,  Condition::Or(Box::new(Condition::rand(rng)), Box::new(Condition::rand(rng))),
-            2,  Condition::And(Box::new(Condition::rand(rng)), Box::new(Condition::rand(rng))),
-
-            1,  Condition::Less(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            1,  Condition::LessEqual(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            1,  Condition::Greater(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            1,  Condition::GreaterEqual(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            2,  Condition::Equal(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng)))
-        ]
+        Condition::weighted(&GrammarWeights::new(), rng)
     }
 }
 
 impl rand::Rand for Command {
-    fn rand<R: rand::Rng>(_: &mut R) -> Self {
-        pick![1, Command::Skip, 1, Command::Left, 1, Command::Right, 1, Command::Thrust]
+    fn rand<R: rand::Rng>(rng: &mut R) -> Self {
+        Command::weighted(&GrammarWeights::new(), rng)
     }
 }
 
 impl rand::Rand for Expression {
     fn rand<R: rand::Rng>(rng: &mut R) -> Self {
-        pick![
-            5, Expression::Constant(rng.next_f32()),
-            5, Expression::Sensor(Box::new(Sensor::rand(rng))),
-            1, Expression::Plus(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            1, Expression::Minus(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            1, Expression::Multiply(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng))),
-            1, Expression::Divide(Box::new(Expression::rand(rng)), Box::new(Expression::rand(rng)))
-        ]
+        Expression::weighted(&GrammarWeights::new(), rng)
     }
 }
 
 impl rand::Rand for Sensor {
-    fn rand<R: rand::Rng>(_: &mut R) -> Self {
-        pick![
-            1, Sensor::Y,
-            1, Sensor::Vy,
-            1, Sensor::Fuel,
-            1, Sensor::X,
-            1, Sensor::Vx,
-            1, Sensor::O,
-            1, Sensor::W
-            ]
-	}
+    fn rand<R: rand::Rng>(rng: &mut R) -> Self {
+        Sensor::weighted(&GrammarWeights::new(), rng)
+    }
+}
+
+/// The way a `Generator` shapes trees
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub enum Method {
+    /// Every node is picked from all node types that fit in the remaining depth, so branches end
+    /// at different depths.
+    Grow,
+    /// Only function nodes are picked until the maximum depth, so every branch is equally deep.
+    Full,
+    /// The maximum depth is picked between the minimum and maximum depth for every tree, and half
+    /// of the trees are created with `Grow`, the other half with `Full`.
+    RampedHalfAndHalf,
+}
+
+/// Creates random trees with a depth, as measured by `ast::depth::Depth`, between `min_depth` and
+/// `max_depth`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate ast;
+/// extern crate rand;
+///
+/// use ast::depth::Depth;
+/// use ast::random::{Generator, Method};
+/// use ast::structure::Program;
+///
+/// fn main() {
+///     let generator = Generator::new().with_min_depth(3).with_max_depth(6).with_method(Method::Full);
+///     let program: Program = generator.generate(&mut rand::thread_rng());
+///     assert!(3 <= program.depth() && program.depth() <= 6);
+/// }
+/// ```
+#[derive(Clone,Copy,Debug,RustcEncodable,RustcDecodable)]
+pub struct Generator {
+    pub min_depth: u32,
+    pub max_depth: u32,
+    pub method: Method,
+    pub weights: GrammarWeights
+}
+
+impl Generator {
+    /// Creates a builder for `Generator`. It should be used in the following sense:
+    ///
+    /// ```
+    /// let generator = ast::random::Generator::new().with_max_depth(8);
+    /// ```
+    pub fn new() -> Generator {
+        Generator {
+            min_depth: 2,
+            max_depth: 6,
+            method: Method::RampedHalfAndHalf,
+            weights: GrammarWeights::new()
+        }
+    }
+
+    pub fn with_min_depth(self, min_depth: u32) -> Generator {
+        Generator { min_depth: min_depth, ..self }
+    }
+
+    pub fn with_max_depth(self, max_depth: u32) -> Generator {
+        Generator { max_depth: max_depth, ..self }
+    }
+
+    pub fn with_method(self, method: Method) -> Generator {
+        Generator { method: method, ..self }
+    }
+
+    pub fn with_weights(self, weights: GrammarWeights) -> Generator {
+        Generator { weights: weights, ..self }
+    }
+
+    /// Create a single random tree
+    pub fn generate<T: Generate, R: rand::Rng>(&self, rng: &mut R) -> T {
+        let max_depth = max(self.min_depth, self.max_depth);
+        match self.method {
+            Method::Grow => T::generate(self, rng, max_depth, self.min_depth, false),
+            Method::Full => T::generate(self, rng, max_depth, max_depth, true),
+            Method::RampedHalfAndHalf => {
+                let depth = rng.gen_range(self.min_depth, max_depth + 1);
+                if rng.gen() {
+                    T::generate(self, rng, depth, self.min_depth, false)
+                } else {
+                    T::generate(self, rng, depth, depth, true)
+                }
+            }
+        }
+    }
+}
+
+/// Trees that can be created by a `Generator`
+pub trait Generate: Sized {
+    /// Create a tree no deeper than `depth` and, where the grammar allows it, at least `min_depth`
+    /// deep. With `full` set, function nodes are preferred over terminals wherever they fit.
+    fn generate<R: rand::Rng>(generator: &Generator, rng: &mut R, depth: u32, min_depth: u32, full: bool) -> Self;
+}
+
+/// A candidate node for a `Generator`: its weight, its own minimal depth, and whether it has
+/// children that can make it deeper.
+struct Candidate {
+    weight: u32,
+    depth: u32,
+    function: bool
+}
+
+fn candidate(weight: u32, depth: u32, function: bool) -> Candidate {
+    Candidate { weight: weight, depth: depth, function: function }
+}
+
+/// Pick the index of a candidate that fits between `min_depth` and `depth`
+fn choose<R: rand::Rng>(rng: &mut R, candidates: &[Candidate], depth: u32, min_depth: u32, full: bool) -> usize {
+    let function_fits = candidates.iter().any(|c| c.function && c.depth <= depth);
+    let fits = |c: &Candidate| {
+        let within = c.depth <= depth;
+        let deep_enough = c.function || c.depth >= min(min_depth, depth);
+        let full_enough = !full || c.function || !function_fits || c.depth == depth;
+        within && deep_enough && full_enough
+    };
+    let weights = candidates.iter()
+        .map(|c| if fits(c) { c.weight } else { 0 })
+        .collect::<Vec<u32>>();
+
+    let total = weights.iter().fold(0, |acc, w| acc + w);
+    if total == 0 {
+        // The constraints can not be met, fall back to the shallowest node that is switched on
+        let enabled = (0..candidates.len()).filter(|&i| candidates[i].weight > 0).collect::<Vec<usize>>();
+        let fallback = if enabled.is_empty() { (0..candidates.len()).collect() } else { enabled };
+        return fallback.into_iter().min_by_key(|&i| candidates[i].depth).unwrap();
+    }
+
+    let mut random_number = rng.gen_range(0, total);
+    for (i, weight) in weights.into_iter().enumerate() {
+        if random_number < weight {
+            return i;
+        }
+        random_number -= weight;
+    }
+    unreachable!()
+}
+
+impl Generate for Program {
+    fn generate<R: rand::Rng>(generator: &Generator, rng: &mut R, depth: u32, min_depth: u32, full: bool) -> Self {
+        let w = &generator.weights.program;
+        let candidates = [
+            candidate(w.iff, 3, true),
+            candidate(w.command, 2, false),
+        ];
+        let (d, m) = (depth.saturating_sub(1), min_depth.saturating_sub(1));
+        match choose(rng, &candidates, depth, min_depth, full) {
+            0 => Program::If(Box::new(Condition::generate(generator, rng, d, m, full)),
+                             Box::new(Program::generate(generator, rng, d, m, full)),
+                             Box::new(Program::generate(generator, rng, d, m, full))),
+            _ => Program::Command(Box::new(Command::weighted(&generator.weights, rng))),
+        }
+    }
+}
+
+impl Generate for Condition {
+    fn generate<R: rand::Rng>(generator: &Generator, rng: &mut R, depth: u32, min_depth: u32, full: bool) -> Self {
+        let w = &generator.weights.condition;
+        let candidates = [
+            candidate(w.true_literal, 1, false),
+            candidate(w.false_literal, 1, false),
+
+            candidate(w.not, 2, true),
+            candidate(w.or, 2, true),
+            candidate(w.and, 2, true),
+
+            candidate(w.less, 2, true),
+            candidate(w.less_equal, 2, true),
+            candidate(w.greater, 2, true),
+            candidate(w.greater_equal, 2, true),
+            candidate(w.equal, 2, true),
+        ];
+        let (d, m) = (depth.saturating_sub(1), min_depth.saturating_sub(1));
+        let picked = choose(rng, &candidates, depth, min_depth, full);
+        let mut condition = || Box::new(Condition::generate(generator, rng, d, m, full));
+        match picked {
+            0 => Condition::True,
+            1 => Condition::False,
+
+            2 => Condition::Not(condition()),
+            3 => Condition::Or(condition(), condition()),
+            4 => Condition::And(condition(), condition()),
+
+            i => {
+                let mut expression = || Box::new(Expression::generate(generator, rng, d, m, full));
+                match i {
+                    5 => Condition::Less(expression(), expression()),
+                    6 => Condition::LessEqual(expression(), expression()),
+                    7 => Condition::Greater(expression(), expression()),
+                    8 => Condition::GreaterEqual(expression(), expression()),
+                    _ => Condition::Equal(expression(), expression()),
+                }
+            }
+        }
+    }
+}
+
+impl Generate for Expression {
+    fn generate<R: rand::Rng>(generator: &Generator, rng: &mut R, depth: u32, min_depth: u32, full: bool) -> Self {
+        let w = &generator.weights.expression;
+        let candidates = [
+            candidate(w.constant, 1, false),
+            candidate(w.sensor, 2, false),
+            candidate(w.plus, 2, true),
+            candidate(w.minus, 2, true),
+            candidate(w.multiply, 2, true),
+            candidate(w.divide, 2, true),
+        ];
+        let (d, m) = (depth.saturating_sub(1), min_depth.saturating_sub(1));
+        match choose(rng, &candidates, depth, min_depth, full) {
+            0 => Expression::Constant(rng.next_f32()),
+            1 => Expression::Sensor(Box::new(Sensor::weighted(&generator.weights, rng))),
+            i => {
+                let mut expression = || Box::new(Expression::generate(generator, rng, d, m, full));
+                match i {
+                    2 => Expression::Plus(expression(), expression()),
+                    3 => Expression::Minus(expression(), expression()),
+                    4 => Expression::Multiply(expression(), expression()),
+                    _ => Expression::Divide(expression(), expression()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+    use depth::Depth;
+    use std::path::Path;
+    use structure::{Program, Condition, Expression, Sensor};
+
+    fn depths<T: Generate+Depth>(generator: Generator) -> Vec<u32> {
+        let mut rng = rand::thread_rng();
+        (0..200).map(|_| generator.generate::<T, _>(&mut rng).depth()).collect()
+    }
+
+    #[test]
+    fn grown_trees_stay_within_depth_bounds() {
+        let generator = Generator::new().with_min_depth(4).with_max_depth(7).with_method(Method::Grow);
+
+        for depth in depths::<Program>(generator).into_iter()
+            .chain(depths::<Condition>(generator))
+            .chain(depths::<Expression>(generator)) {
+            assert!(4 <= depth && depth <= 7, "depth {} out of bounds", depth);
+        }
+    }
+
+    #[test]
+    fn full_trees_have_the_maximum_depth() {
+        let generator = Generator::new().with_min_depth(2).with_max_depth(6).with_method(Method::Full);
+
+        assert!(depths::<Program>(generator).into_iter().all(|d| d == 6));
+        assert!(depths::<Condition>(generator).into_iter().all(|d| d == 6));
+        assert!(depths::<Expression>(generator).into_iter().all(|d| d == 6));
+    }
+
+    #[test]
+    fn ramped_half_and_half_covers_all_depths() {
+        let generator = Generator::new().with_min_depth(3).with_max_depth(5);
+        let depths = depths::<Program>(generator);
+
+        assert!(depths.iter().all(|&d| 3 <= d && d <= 5));
+        for depth in 3..6 {
+            assert!(depths.contains(&depth));
+        }
+    }
+
+    #[test]
+    fn default_weights_file_matches_the_defaults() {
+        let weights = GrammarWeights::load_file(Path::new("resources/weights.json")).unwrap();
+
+        assert_eq!(weights, GrammarWeights::new());
+    }
+
+    #[test]
+    fn switched_off_sensors_are_never_generated() {
+        let mut weights = GrammarWeights::new();
+        weights.sensor.fuel = 0;
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..1000 {
+            assert!(Sensor::weighted(&weights, &mut rng) != Sensor::Fuel);
+        }
+    }
+
+    #[test]
+    fn weights_without_alternatives_are_rejected() {
+        let mut weights = GrammarWeights::new();
+        weights.command = CommandWeights { skip: 0, left: 0, right: 0, thrust: 0 };
+
+        assert!(weights.validate().is_err());
+    }
 }
@@ -13,16 +13,22 @@ use std::path::Path;
 use std::error::Error;
 
 /// A collection of game states in a trace
-#[derive(Clone,RustcEncodable)]
+///
+/// Next to the true states, a trace can hold what the program observed of them. This is left
+/// empty when the program saw the exact states. Otherwise the observation at an index belongs to
+/// the state at that index, and the last state has none when the episode ended on it.
+#[derive(Clone,RustcEncodable,RustcDecodable)]
 pub struct GameTrace {
-    states: Vec<SensorData>
+    states: Vec<SensorData>,
+    observed: Vec<SensorData>
 }
 
 impl GameTrace {
     pub fn new() -> GameTrace {
         GameTrace {
             // A reasonable capacity to avoid common reallocations
-            states: Vec::with_capacity(200)
+            states: Vec::with_capacity(200),
+            observed: Vec::new()
         }
     }
 
@@ -30,10 +36,18 @@ impl GameTrace {
         self.states.push(sensor_data.clone());
     }
 
+    pub fn add_observed(&mut self, sensor_data: &SensorData) {
+        self.observed.push(sensor_data.clone());
+    }
+
     pub fn trace(&self) -> &Vec<SensorData> {
         &self.states
     }
 
+    pub fn observed(&self) -> &Vec<SensorData> {
+        &self.observed
+    }
+
     pub fn save(&self, w: &mut Write) -> Result<(), Box<Error>> {
         let encoded = try!(json::encode(&self.states));
         try!(w.write_all(&encoded.as_bytes()));
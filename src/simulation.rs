@@ -1,5 +1,11 @@
 use super::structure::{Program, Command, Condition, Number};
 use super::data::{SensorData, Evaluate, BooleanValue};
+use super::serialize::GameTrace;
+use super::terrain::Terrain;
+use super::integrator::{Integrator, Forces, Motion};
+use super::disturbance::{Disturbances, Gust, Push};
+use super::observation::{Observation, Observer};
+use rand::Rng;
 
 fn abs(x: Number) -> Number {
     if x < 0.0 {
@@ -9,6 +15,11 @@ fn abs(x: Number) -> Number {
     }
 }
 
+/// The physics of the simulation
+///
+/// The constants are per unit of time. A frame, in which the program is evaluated once, lasts
+/// `dt` units of time and is integrated in `substeps` steps. The defaults of a frame of 1 unit in
+/// a single semi-implicit Euler step reproduce the original frame update.
 #[derive(Clone,Copy)]
 pub struct World {
     pub angular_increment: Number,
@@ -17,7 +28,19 @@ pub struct World {
     pub tolerance: Number,
     pub fuel_consumption: Number,
     pub max_landing_angle_rads: Number,
-    pub max_landing_speed: Number
+    pub max_landing_speed: Number,
+    pub terrain: Terrain,
+    /// Duration of a frame
+    pub dt: Number,
+    /// Number of integration steps per frame
+    pub substeps: u32,
+    pub integrator: Integrator,
+    /// Applied by the disturbed variants of the step functions, the others panic unless they
+    /// are calm
+    pub disturbances: Disturbances,
+    /// What programs see of the state in a disturbed episode, the undisturbed step functions
+    /// panic unless it is exact
+    pub observation: Observation
 }
 
 impl World {
@@ -34,7 +57,13 @@ impl World {
             tolerance: 0.01,
             fuel_consumption: 0.01,
             max_landing_angle_rads: 0.1,
-            max_landing_speed: 0.5
+            max_landing_speed: 0.5,
+            terrain: Terrain::everywhere(),
+            dt: 1.0,
+            substeps: 1,
+            integrator: Integrator::SemiImplicitEuler,
+            disturbances: Disturbances::new(),
+            observation: Observation::new()
         }
     }
 
@@ -65,9 +94,82 @@ impl World {
     pub fn with_fuel_consumption(self, fuel_consumption: Number) -> World {
         World { fuel_consumption: fuel_consumption, ..self}
     }
+
+    pub fn with_terrain(self, terrain: Terrain) -> World {
+        World { terrain: terrain, ..self}
+    }
+
+    pub fn with_dt(self, dt: Number) -> World {
+        World { dt: dt, ..self}
+    }
+
+    pub fn with_substeps(self, substeps: u32) -> World {
+        World { substeps: substeps, ..self}
+    }
+
+    pub fn with_integrator(self, integrator: Integrator) -> World {
+        World { integrator: integrator, ..self}
+    }
+
+    pub fn with_disturbances(self, disturbances: Disturbances) -> World {
+        World { disturbances: disturbances, ..self}
+    }
+
+    pub fn with_observation(self, observation: Observation) -> World {
+        World { observation: observation, ..self}
+    }
+}
+
+/// Fill in the sensors that depend on the terrain beneath the lander
+pub fn sense_terrain(sensor_data: &mut SensorData, world: &World) {
+    sensor_data.altitude = world.terrain.altitude(sensor_data.x, sensor_data.y);
+    sensor_data.pad_distance = world.terrain.pad_distance(sensor_data.x);
 }
 
+/// Number of halvings used to find the moment of touchdown within a step
+const TOUCHDOWN_ITERATIONS: u32 = 24;
+
+/// Fraction of a step of h after which a lander that starts above the ground touches it
+///
+/// The step is bisected with the integrator of the world, so the moment is found on the same
+/// trajectory that the step follows.
+fn touchdown(world: &World, before: Motion, forces: &Forces, h: Number) -> Number {
+    let below = |fraction: Number| {
+        let m = world.integrator.step(before, forces, fraction * h);
+        world.terrain.altitude(m.x, m.y) < 0.0
+    };
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..TOUCHDOWN_ITERATIONS {
+        let mid = (lo + hi) / 2.0;
+        if below(mid) { hi = mid; } else { lo = mid; }
+    }
+    lo
+}
+
+/// Advance the lander by a frame, in a world without disturbances
+///
+/// A step that ends below the ground is cut short at the moment of touchdown, so the lander ends
+/// on the surface with the velocity and the orientation it had on contact. A lander that starts
+/// a step on the ground, or ends the frame within the tolerance of it, is put on the surface.
+///
+/// Panics if the world has disturbances.
 pub fn update_data(sensor_data: &mut SensorData, command: Command, world: &World) {
+    assert!(world.disturbances.is_calm(), "a world with disturbances needs update_data_disturbed");
+    advance(sensor_data, command, world, &Push::none());
+}
+
+/// Advance the lander by a frame, disturbed as the world describes
+///
+/// The gust carries over from frame to frame of an episode, and all random numbers are drawn
+/// from rng.
+pub fn update_data_disturbed<R: Rng>(sensor_data: &mut SensorData, command: Command, world: &World, gust: &mut Gust, rng: &mut R) {
+    if sensor_data.hit_ground { return; }
+
+    let push = world.disturbances.draw(gust, world.dt, rng);
+    advance(sensor_data, command, world, &push);
+}
+
+fn advance(sensor_data: &mut SensorData, command: Command, world: &World, push: &Push) {
     if sensor_data.hit_ground { return; }
 
     let angular_multiplier: Number = match command {
@@ -75,28 +177,48 @@ pub fn update_data(sensor_data: &mut SensorData, command: Command, world: &World
         Command::Right => -0.2,
         _              =>  0.0
     };
-    sensor_data.w += angular_multiplier * world.angular_increment;
-    sensor_data.o += sensor_data.w;
-
-    let thrust_multiplier: Number = match command {
-        Command::Thrust => { if sensor_data.fuel > 0.0 { 1.0 } else { 0.0 } },
-        _               => 0.0
-    };
-    let acceleration = thrust_multiplier * world.thrust_constant;
-    let ax = -acceleration * sensor_data.o.sin();
-    let ay = acceleration * sensor_data.o.cos() + world.gravitational_constant;
-    sensor_data.vx += ax;
-    sensor_data.vy += ay;
-    sensor_data.x += sensor_data.vx;
-    sensor_data.y += sensor_data.vy;
-
-    sensor_data.fuel -= match command {
-        Command::Thrust => world.fuel_consumption,
-        _               => 0.0
+    let thrusting = match command {
+        Command::Thrust => true,
+        _               => false
     };
-    sensor_data.fuel = if sensor_data.fuel > 0.0 { sensor_data.fuel } else { 0.0 };
 
-    let down = sensor_data.y < world.tolerance;
+    let steps = if world.substeps > 0 { world.substeps } else { 1 };
+    let h = world.dt / steps as Number;
+    for _ in 0..steps {
+        let forces = Forces {
+            thrust: if thrusting && sensor_data.fuel > 0.0 { world.thrust_constant * push.thrust_factor } else { 0.0 },
+            gravity: world.gravitational_constant,
+            push_x: push.ax,
+            push_y: push.ay,
+            angular_acceleration: angular_multiplier * world.angular_increment + push.angular_acceleration
+        };
+        let before = Motion::of(sensor_data);
+        let mut after = world.integrator.step(before, &forces, h);
+        let mut duration = h;
+        if world.terrain.altitude(after.x, after.y) < 0.0 {
+            if world.terrain.altitude(before.x, before.y) > 0.0 {
+                duration = h * touchdown(world, before, &forces, h);
+                after = world.integrator.step(before, &forces, duration);
+            }
+            after.y = world.terrain.ground_height(after.x);
+        }
+        after.apply(sensor_data);
+
+        if thrusting {
+            sensor_data.fuel -= world.fuel_consumption * duration;
+        }
+        sensor_data.fuel = if sensor_data.fuel > 0.0 { sensor_data.fuel } else { 0.0 };
+
+        sense_terrain(sensor_data, world);
+        if sensor_data.altitude < world.tolerance { break; }
+    }
+
+    let down = sensor_data.altitude < world.tolerance;
+    if down {
+        sensor_data.y = world.terrain.ground_height(sensor_data.x);
+        sensor_data.altitude = 0.0;
+    }
+    let on_pad = world.terrain.pad_at(sensor_data.x).is_some();
     let upright = abs(sensor_data.o) < world.max_landing_angle_rads;
     let crashed = abs(sensor_data.vy) > world.max_landing_speed;
     if down && crashed {
@@ -104,31 +226,166 @@ pub fn update_data(sensor_data: &mut SensorData, command: Command, world: &World
     }
 
     sensor_data.hit_ground = down;
-    sensor_data.landed     = down && upright && !crashed;
+    sensor_data.landed     = down && on_pad && upright && !crashed;
 
     // No thrusting anymore if we touched down, it looks weird in the animation :)
-    sensor_data.thrusting = !down && match command {
-        Command::Thrust => true,
-        _               => false
-    }
+    sensor_data.thrusting = !down && thrusting;
 }
 
 pub fn next_program(sensor_data: &mut SensorData, program: &Program, world: &World) {
+    assert_exact(world);
     let command = program.evaluate(*sensor_data);
     update_data(sensor_data, command, world);
 }
 
 pub fn next_condition(sensor_data: &mut SensorData, cond: &Condition, world: &World) {
+    assert_exact(world);
     let result = cond.value(*sensor_data);
     update_data(sensor_data, if result { Command::Thrust } else { Command::Skip }, world);
 }
 
+fn assert_exact(world: &World) {
+    assert!(world.observation.is_exact(), "a world with inexact sensors needs run_disturbed_episode");
+}
+
+/// Bounds on a single episode, so that programs that never touch down still terminate
+#[derive(Clone,Copy)]
+pub struct Limits {
+    pub max_frames: u32,
+    pub max_altitude: Number,
+    pub max_distance: Number,
+    pub stop_when_out_of_fuel: bool
+}
+
+impl Limits {
+    /// Creates a builder for `Limits`. It should be used in the following sense:
+    ///
+    /// ```
+    /// let limits = ast::simulation::Limits::new().with_max_frames(500);
+    /// ```
+    pub fn new() -> Limits {
+        Limits {
+            max_frames: 10000,
+            max_altitude: 10000.0,
+            max_distance: 10000.0,
+            stop_when_out_of_fuel: false
+        }
+    }
+
+    pub fn with_max_frames(self, max_frames: u32) -> Limits {
+        Limits { max_frames: max_frames, ..self }
+    }
+
+    pub fn with_max_altitude(self, max_altitude: Number) -> Limits {
+        Limits { max_altitude: max_altitude, ..self }
+    }
+
+    pub fn with_max_distance(self, max_distance: Number) -> Limits {
+        Limits { max_distance: max_distance, ..self }
+    }
+
+    pub fn stop_when_out_of_fuel(self) -> Limits {
+        Limits { stop_when_out_of_fuel: true, ..self }
+    }
+}
+
+/// The way an episode ended
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable)]
+pub enum Outcome {
+    /// Touched down slowly and upright on a landing pad
+    Landed,
+    /// Touched down too fast, at too steep an angle or next to the landing pads
+    Crashed { speed: Number, angle: Number },
+    /// Still airborne after `Limits::max_frames` frames
+    Timeout,
+    /// Flew above `Limits::max_altitude` or further than `Limits::max_distance` from the origin
+    OutOfBounds,
+    /// Ran out of fuel while airborne, only when `Limits::stop_when_out_of_fuel` is set
+    FuelExhausted
+}
+
+/// Run a program from a start position until the episode ends, in a world without disturbances
+///
+/// The returned trace contains the start position and every frame after it. The terrain sensors
+/// of the start position are filled in from the world. The program sees the exact state, so the
+/// trace holds no observations.
+///
+/// Panics if the world has disturbances or sensors that are not exact.
+pub fn run_episode<E: Evaluate>(program: &E, start: SensorData, world: &World, limits: &Limits) -> (GameTrace, Outcome) {
+    assert_exact(world);
+    episode(start, world, limits, |sensor_data| {
+        let command = program.evaluate(*sensor_data);
+        update_data(sensor_data, command, world);
+        None
+    })
+}
+
+/// Run a program from a start position until the episode ends, disturbed as the world describes
+///
+/// The program sees the state through the observation of the world. Unless the observation is
+/// exact, the trace records what it saw next to the true states. The same seed of the RNG gives
+/// the same episode.
+pub fn run_disturbed_episode<E: Evaluate, R: Rng>(program: &E, start: SensorData, world: &World, limits: &Limits, rng: &mut R) -> (GameTrace, Outcome) {
+    let mut gust = Gust::new();
+    let mut observer = Observer::new(world.observation);
+    let exact = world.observation.is_exact();
+    episode(start, world, limits, |sensor_data| {
+        let observed = observer.observe(sensor_data, rng);
+        update_data_disturbed(sensor_data, program.evaluate(observed), world, &mut gust, rng);
+        if exact { None } else { Some(observed) }
+    })
+}
+
+/// Run frames until the episode ends, where a frame advances the state and returns what the
+/// program observed of it, if that is worth recording
+fn episode<F>(start: SensorData, world: &World, limits: &Limits, mut frame: F) -> (GameTrace, Outcome)
+    where F: FnMut(&mut SensorData) -> Option<SensorData>
+{
+    let mut sensor_data = start;
+    let mut trace = GameTrace::new();
+    let mut frames = 0;
+
+    sense_terrain(&mut sensor_data, world);
+
+    trace.add(&sensor_data);
+    loop {
+        if sensor_data.hit_ground {
+            let outcome = if sensor_data.landed {
+                Outcome::Landed
+            } else {
+                Outcome::Crashed { speed: abs(sensor_data.vy), angle: sensor_data.o }
+            };
+            return (trace, outcome);
+        }
+        if frames >= limits.max_frames {
+            return (trace, Outcome::Timeout);
+        }
+        if sensor_data.y > limits.max_altitude || abs(sensor_data.x) > limits.max_distance {
+            return (trace, Outcome::OutOfBounds);
+        }
+        if limits.stop_when_out_of_fuel && sensor_data.fuel <= 0.0 {
+            return (trace, Outcome::FuelExhausted);
+        }
+
+        if let Some(observed) = frame(&mut sensor_data) {
+            trace.add_observed(&observed);
+        }
+        trace.add(&sensor_data);
+        frames += 1;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::f32;
     use data::{SensorData};
-    use structure::{Program, Command};
+    use structure::{Program, Command, Sensor};
+    use terrain::{Terrain, Pad};
+    use integrator::Integrator;
+    use disturbance::Disturbances;
+    use observation::{Observation, SensorModel};
+    use rand::{SeedableRng, StdRng};
 
     #[test]
     fn next_should_land_if_all_motion_is_stopped_and_near_horizon() {
@@ -253,4 +510,144 @@ mod tests {
 
         assert!(sensor_data.vx == 0.0);
     }
+
+    #[test]
+    fn trajectory_should_not_depend_on_the_frame_duration() {
+        let program = Program::Command(Box::new(Command::Thrust));
+        let start = SensorData::new().with_y(100.0).with_vx(1.0).with_o(0.2).with_fuel(1.0);
+        let frames = |world: &World, n: u32| {
+            let mut sensor_data = start;
+            for _ in 0..n { next_program(&mut sensor_data, &program, world); }
+            sensor_data
+        };
+
+        let coarse = frames(&World::new().with_integrator(Integrator::RungeKutta4), 10);
+        let fine = frames(&World::new().with_integrator(Integrator::RungeKutta4).with_dt(0.25).with_substeps(2), 40);
+
+        assert!((coarse.x - fine.x).abs() < 1e-3);
+        assert!((coarse.y - fine.y).abs() < 1e-3);
+        assert!((coarse.vy - fine.vy).abs() < 1e-3);
+        assert!((coarse.fuel - fine.fuel).abs() < 1e-4);
+    }
+
+    #[test]
+    fn disturbed_episodes_should_follow_the_seed() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let world = World::new().with_disturbances(Disturbances::new().with_gusts(0.2, 5.0).with_torque(0.01));
+        let start = SensorData::new().with_y(100.0);
+        let path = |trace: GameTrace| trace.trace().iter().map(|s| (s.x, s.y, s.o)).collect::<Vec<_>>();
+        let episode = |seed: usize| path(run_disturbed_episode(&program, start, &world, &Limits::new(), &mut StdRng::from_seed(&[seed])).0);
+
+        assert_eq!(episode(1), episode(1));
+        assert!(episode(1) != episode(2));
+        assert!(episode(1) != path(run_episode(&program, start, &World::new(), &Limits::new()).0));
+    }
+
+    #[test]
+    fn programs_should_see_the_observed_state() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let world = World::new().with_observation(Observation::new().with(Sensor::Y, SensorModel::exact().with_quantum(10.0)));
+        let (trace, _) = run_disturbed_episode(&program, SensorData::new().with_y(34.0), &world, &Limits::new(), &mut StdRng::from_seed(&[1]));
+
+        assert_eq!(trace.frames() - 1, trace.observed().len());
+        assert_eq!(34.0, trace.trace()[0].y);
+        assert_eq!(30.0, trace.observed()[0].y);
+        assert!(trace.observed().iter().zip(trace.trace()).all(|(seen, state)| seen.y % 10.0 == 0.0 && seen.vy == state.vy));
+
+        let (trace, _) = run_episode(&program, SensorData::new().with_y(34.0), &World::new(), &Limits::new());
+        assert!(trace.observed().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "a world with disturbances needs update_data_disturbed")]
+    fn undisturbed_episodes_should_refuse_disturbances() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let world = World::new().with_disturbances(Disturbances::new().with_wind(0.1, 0.0));
+
+        run_episode(&program, SensorData::new().with_y(10.0), &world, &Limits::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "a world with inexact sensors needs run_disturbed_episode")]
+    fn undisturbed_episodes_should_refuse_inexact_sensors() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let world = World::new().with_observation(Observation::new().with_all(SensorModel::exact().with_delay(1)));
+
+        run_episode(&program, SensorData::new().with_y(10.0), &world, &Limits::new());
+    }
+
+    #[test]
+    fn run_episode_should_report_landing() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let (trace, outcome) = run_episode(&program, SensorData::new().with_vy(0.5), &World::new(), &Limits::new());
+
+        assert_eq!(Outcome::Landed, outcome);
+        assert_eq!(2, trace.frames());
+    }
+
+    #[test]
+    fn touchdown_next_to_a_pad_should_not_be_a_landing() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let world = World::new().with_terrain(Terrain::new().with_pad(Pad::new(10.0, 20.0, 5.0)));
+
+        let (_, outcome) = run_episode(&program, SensorData::new().with_vy(0.5), &world, &Limits::new());
+        match outcome {
+            Outcome::Crashed { .. } => (),
+            _ => panic!("expected a crash, got {:?}", outcome)
+        }
+
+        let (trace, outcome) = run_episode(&program, SensorData::new().with_x(15.0).with_y(5.0).with_vy(0.5), &world, &Limits::new());
+        assert_eq!(Outcome::Landed, outcome);
+        assert_eq!(0.0, trace.trace()[0].altitude);
+        assert_eq!(0.0, trace.trace()[0].pad_distance);
+    }
+
+    #[test]
+    fn touchdown_should_end_on_the_surface_with_the_contact_speed() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let world = World::new().with_integrator(Integrator::RungeKutta4);
+        let (trace, outcome) = run_episode(&program, SensorData::new().with_y(100.0), &world, &Limits::new());
+
+        let last = trace.trace()[trace.frames() - 1];
+        assert_eq!(0.0, last.y);
+        assert!((last.vy + 10.0).abs() < 1e-3, "vy {}", last.vy);
+        assert_eq!(Outcome::Crashed { speed: last.crash_speed, angle: 0.0 }, outcome);
+    }
+
+    #[test]
+    fn run_episode_should_report_crash_speed_and_angle() {
+        let program = Program::Command(Box::new(Command::Skip));
+        let (_, outcome) = run_episode(&program, SensorData::new().with_y(100.0).with_o(0.5), &World::new(), &Limits::new());
+
+        match outcome {
+            Outcome::Crashed { speed, angle } => {
+                assert!(speed > 0.5);
+                assert_eq!(0.5, angle);
+            },
+            _ => panic!("expected a crash, got {:?}", outcome)
+        }
+    }
+
+    #[test]
+    fn run_episode_should_stop_programs_that_never_come_down() {
+        let program = Program::Command(Box::new(Command::Thrust));
+        let world = World::new().with_gravitational_constant(0.0).with_fuel_consumption(0.0);
+
+        let (trace, outcome) = run_episode(&program, SensorData::new().with_y(10.0), &world, &Limits::new().with_max_frames(50));
+        assert_eq!(Outcome::Timeout, outcome);
+        assert_eq!(51, trace.frames());
+
+        let (_, outcome) = run_episode(&program, SensorData::new().with_y(10.0), &world, &Limits::new().with_max_altitude(20.0));
+        assert_eq!(Outcome::OutOfBounds, outcome);
+    }
+
+    #[test]
+    fn run_episode_should_stop_when_out_of_fuel_if_asked() {
+        let program = Program::Command(Box::new(Command::Thrust));
+        let limits = Limits::new().stop_when_out_of_fuel();
+
+        let (_, outcome) = run_episode(&program, SensorData::new().with_y(100.0), &World::new(), &limits);
+
+        assert_eq!(Outcome::FuelExhausted, outcome);
+    }
 }
@@ -0,0 +1,78 @@
+//! Count the nodes of an expression tree
+//!
+//! The size of a tree is the number of nodes in it, including the leaves. A sensor or a command
+//! is a node of its own, next to the expression or program that holds it. Together with
+//! `depth::Depth` it is used to keep programs from growing without bound.
+
+use super::structure::{Program,Condition,Command,Expression,Sensor};
+
+pub trait Size {
+    fn size(&self) -> u32;
+}
+
+impl Size for Program {
+    fn size(&self) -> u32 {
+		1 + match *self {
+			Program::If(ref condition, ref left, ref right) => condition.size() + left.size() + right.size(),
+			Program::Command(ref command) => command.size()
+		}
+    }
+}
+
+impl Size for Condition {
+    fn size(&self) -> u32 {
+		1 + match *self {
+			Condition::Not(ref condition)                => condition.size(),
+			Condition::Or(ref left, ref right)           => left.size() + right.size(),
+			Condition::And(ref left, ref right)          => left.size() + right.size(),
+			Condition::Less(ref left, ref right)         => left.size() + right.size(),
+			Condition::LessEqual(ref left, ref right)    => left.size() + right.size(),
+			Condition::Equal(ref left, ref right)        => left.size() + right.size(),
+			Condition::GreaterEqual(ref left, ref right) => left.size() + right.size(),
+			Condition::Greater(ref left, ref right)      => left.size() + right.size(),
+            _ => 0
+		}
+    }
+}
+
+impl Size for Expression {
+    fn size(&self) -> u32 {
+		1 + match *self {
+			Expression::Sensor(ref sensor)            => sensor.size(),
+			Expression::Plus(ref left, ref right)     => left.size() + right.size(),
+			Expression::Minus(ref left, ref right)    => left.size() + right.size(),
+			Expression::Multiply(ref left, ref right) => left.size() + right.size(),
+			Expression::Divide(ref left, ref right)   => left.size() + right.size(),
+            _ => 0
+		}
+    }
+}
+
+impl Size for Command {
+    fn size(&self) -> u32 {
+        1
+    }
+}
+
+impl Size for Sensor {
+    fn size(&self) -> u32 {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // This makes the macros work (which expect stuff to be in ast::structure::...etc...)
+    mod ast { pub use super::super::super::structure; }
+
+    #[test]
+    fn size_counts_every_node() {
+        assert_eq!(2, skip!().size());
+        assert_eq!(1, T!().size());
+        assert_eq!(4, less!(vy!(), constant!(1.0)).size());
+        assert_eq!(3, plus!(constant!(1.0), constant!(2.0)).size());
+        assert_eq!(11, iff!(not!(greater!(y!(), fuel!())), left!(), thrust!()).size());
+    }
+}
@@ -1,8 +1,26 @@
 //! The `source` module provides a representation of `ast::structure::Program`s
 //!
 //! It defines a trait `Source` that sources a `ast::structure::Program` to a `String`. 
+//!
+//! The functions `parse_program`, `parse_condition` and `parse_expression` do the reverse: they
+//! read the macro notation produced by `Source` back into a tree.
+//!
+//! # Examples
+//!
+//! ```
+//! extern crate ast;
+//!
+//! use ast::source::{Source, parse_program};
+//!
+//! fn main() {
+//!     let program = parse_program("iff!(less!(vy!(),constant!(0.5000)),thrust!(),skip!())").unwrap();
+//!     assert_eq!("iff!(less!(vy!(),constant!(0.5000)),thrust!(),skip!())", program.source());
+//! }
+//! ```
 
-use super::structure::{Program,Condition,Command,Expression,Sensor};
+use std::error::Error;
+use std::fmt;
+use super::structure::{Program,Condition,Command,Expression,Sensor,Number};
 
 /// Representation of an `ast::structure::Program`
 pub trait Source {
@@ -59,6 +77,8 @@ impl Source for Sensor {
                   Sensor::Vx => format!("vx!()"),
                   Sensor::O  => format!("o!()"),
                   Sensor::W  => format!("w!()"),
+                  Sensor::Altitude    => format!("altitude!()"),
+                  Sensor::PadDistance => format!("pad_distance!()"),
 		    }
 	  }
 }
@@ -74,10 +94,272 @@ impl Source for Command {
 	}
 }
 
+/// Error returned when a string can not be parsed into a tree
+///
+/// `line` and `column` are 1-based and point at the offending character.
+#[derive(Debug,Clone,PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Parse the `Source` representation of a `Program`
+pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+    let mut parser = Parser::new(input);
+    let program = try!(parser.program());
+    try!(parser.end());
+    Ok(program)
+}
+
+/// Parse the `Source` representation of a `Condition`
+pub fn parse_condition(input: &str) -> Result<Condition, ParseError> {
+    let mut parser = Parser::new(input);
+    let condition = try!(parser.condition());
+    try!(parser.end());
+    Ok(condition)
+}
+
+/// Parse the `Source` representation of an `Expression`
+pub fn parse_expression(input: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser::new(input);
+    let expression = try!(parser.expression());
+    try!(parser.end());
+    Ok(expression)
+}
+
+/// Recursive descent parser for the macro notation
+///
+/// Whitespace is allowed between tokens, so hand formatted sources are accepted as well.
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    line: usize,
+    column: usize
+}
+
+impl <'a> Parser<'a> {
+    fn new(input: &'a str) -> Parser<'a> {
+        Parser { input: input, position: 0, line: 1, column: 1 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.position += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, |c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn error_at(&self, line: usize, column: usize, message: String) -> ParseError {
+        ParseError { line: line, column: column, message: message }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        self.error_at(self.line, self.column, message)
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("`{}`", c),
+            None    => "end of input".to_string()
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`, found {}", expected, self.found())))
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error(format!("expected end of input, found {}", self.found())))
+        }
+    }
+
+    /// Read a `name!(` prefix, returning the name and the position it started at
+    fn macro_open(&mut self) -> Result<(&'a str, usize, usize), ParseError> {
+        self.skip_whitespace();
+        let (line, column, start) = (self.line, self.column, self.position);
+        while self.peek().map_or(false, |c| c.is_alphanumeric() || c == '_') {
+            self.bump();
+        }
+        if start == self.position {
+            return Err(self.error(format!("expected a macro, found {}", self.found())));
+        }
+        let name = &self.input[start..self.position];
+        try!(self.expect('!'));
+        try!(self.expect('('));
+        Ok((name, line, column))
+    }
+
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let (name, line, column) = try!(self.macro_open());
+        let program = match name {
+            "iff" => {
+                let condition = try!(self.condition());
+                try!(self.expect(','));
+                let left = try!(self.program());
+                try!(self.expect(','));
+                let right = try!(self.program());
+                Program::If(Box::new(condition), Box::new(left), Box::new(right))
+            },
+            "skip"   => Program::Command(Box::new(Command::Skip)),
+            "left"   => Program::Command(Box::new(Command::Left)),
+            "right"  => Program::Command(Box::new(Command::Right)),
+            "thrust" => Program::Command(Box::new(Command::Thrust)),
+            _ => return Err(self.error_at(line, column, format!("expected a program, found `{}!`", name)))
+        };
+        try!(self.expect(')'));
+        Ok(program)
+    }
+
+    fn condition(&mut self) -> Result<Condition, ParseError> {
+        let (name, line, column) = try!(self.macro_open());
+        let condition = match name {
+            "T"   => Condition::True,
+            "F"   => Condition::False,
+            "not" => Condition::Not(Box::new(try!(self.condition()))),
+            "or"  => {
+                let (left, right) = try!(self.condition_pair());
+                Condition::Or(left, right)
+            },
+            "and" => {
+                let (left, right) = try!(self.condition_pair());
+                Condition::And(left, right)
+            },
+            "less" => {
+                let (left, right) = try!(self.expression_pair());
+                Condition::Less(left, right)
+            },
+            "less_equal" => {
+                let (left, right) = try!(self.expression_pair());
+                Condition::LessEqual(left, right)
+            },
+            "equal" => {
+                let (left, right) = try!(self.expression_pair());
+                Condition::Equal(left, right)
+            },
+            "greater_equal" => {
+                let (left, right) = try!(self.expression_pair());
+                Condition::GreaterEqual(left, right)
+            },
+            "greater" => {
+                let (left, right) = try!(self.expression_pair());
+                Condition::Greater(left, right)
+            },
+            _ => return Err(self.error_at(line, column, format!("expected a condition, found `{}!`", name)))
+        };
+        try!(self.expect(')'));
+        Ok(condition)
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        let (name, line, column) = try!(self.macro_open());
+        let expression = match name {
+            "constant" => Expression::Constant(try!(self.number())),
+            "plus" => {
+                let (left, right) = try!(self.expression_pair());
+                Expression::Plus(left, right)
+            },
+            "minus" => {
+                let (left, right) = try!(self.expression_pair());
+                Expression::Minus(left, right)
+            },
+            "multiply" => {
+                let (left, right) = try!(self.expression_pair());
+                Expression::Multiply(left, right)
+            },
+            "divide" => {
+                let (left, right) = try!(self.expression_pair());
+                Expression::Divide(left, right)
+            },
+            "x"    => Expression::Sensor(Box::new(Sensor::X)),
+            "y"    => Expression::Sensor(Box::new(Sensor::Y)),
+            "vx"   => Expression::Sensor(Box::new(Sensor::Vx)),
+            "vy"   => Expression::Sensor(Box::new(Sensor::Vy)),
+            "o"    => Expression::Sensor(Box::new(Sensor::O)),
+            "w"    => Expression::Sensor(Box::new(Sensor::W)),
+            "fuel" => Expression::Sensor(Box::new(Sensor::Fuel)),
+            "altitude"     => Expression::Sensor(Box::new(Sensor::Altitude)),
+            "pad_distance" => Expression::Sensor(Box::new(Sensor::PadDistance)),
+            _ => return Err(self.error_at(line, column, format!("expected an expression, found `{}!`", name)))
+        };
+        try!(self.expect(')'));
+        Ok(expression)
+    }
+
+    fn condition_pair(&mut self) -> Result<(Box<Condition>, Box<Condition>), ParseError> {
+        let left = try!(self.condition());
+        try!(self.expect(','));
+        let right = try!(self.condition());
+        Ok((Box::new(left), Box::new(right)))
+    }
+
+    fn expression_pair(&mut self) -> Result<(Box<Expression>, Box<Expression>), ParseError> {
+        let left = try!(self.expression());
+        try!(self.expect(','));
+        let right = try!(self.expression());
+        Ok((Box::new(left), Box::new(right)))
+    }
+
+    /// Read a number, including the `NaN` and `inf` values that `Source` can produce
+    fn number(&mut self) -> Result<Number, ParseError> {
+        self.skip_whitespace();
+        let (line, column, start) = (self.line, self.column, self.position);
+        while self.peek().map_or(false, |c| c.is_alphanumeric() || c == '.' || c == '-' || c == '+') {
+            self.bump();
+        }
+        let literal = &self.input[start..self.position];
+        literal.parse::<Number>().map_err(|_| {
+            if literal.is_empty() {
+                self.error(format!("expected a number, found {}", self.found()))
+            } else {
+                self.error_at(line, column, format!("invalid number `{}`", literal))
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use structure::{Program,Condition,Command,Expression,Sensor};
+    use rand;
+    use rand::Rng;
 
     #[test]
     fn should_correctly_source_programs() {
@@ -124,6 +406,8 @@ mod tests {
 	      assert_eq!("y!()",  Sensor::Y.source());
 	      assert_eq!("vy!()", Sensor::Vy.source());
           assert_eq!("fuel!()",  Sensor::Fuel.source());
+          assert_eq!("altitude!()",  Sensor::Altitude.source());
+          assert_eq!("pad_distance!()",  Sensor::PadDistance.source());
 		  //assert_eq!("x!()",  Sensor::X.source());
 		  //assert_eq!("vx!()", Sensor::Vx.source());
 		  //assert_eq!("o!()",  Sensor::O.source());
@@ -137,4 +421,44 @@ mod tests {
 	      assert_eq!("right!()", Command::Right.source());
 	      assert_eq!("thrust!()",    Command::Thrust.source());
     }
+
+    #[test]
+    fn should_parse_sourced_programs() {
+        let program = Program::If(
+            Box::new(Condition::Less(Box::new(Expression::Sensor(Box::new(Sensor::Vy))), Box::new(Expression::Constant(0.5)))),
+            Box::new(Program::Command(Box::new(Command::Thrust))),
+            Box::new(Program::Command(Box::new(Command::Skip))));
+
+        assert_eq!(Ok(program), parse_program("iff!(less!(vy!(),constant!(0.5000)),thrust!(),skip!())"));
+    }
+
+    #[test]
+    fn should_parse_sourced_conditions_and_expressions() {
+        assert_eq!(Ok(Condition::Not(Box::new(Condition::True))), parse_condition("not!(T!())"));
+        assert_eq!(Ok(Expression::Divide(Box::new(Expression::Sensor(Box::new(Sensor::Fuel))), Box::new(Expression::Constant(-2.0)))),
+                   parse_expression("divide!(fuel!(),constant!(-2.0000))"));
+        assert_eq!(Ok(Expression::Constant(Number::INFINITY)), parse_expression("constant!(inf)"));
+    }
+
+    #[test]
+    fn should_report_line_and_column_of_parse_errors() {
+        let error = parse_program("iff!(T!(),\n     skip!(),\n     jump!())").unwrap_err();
+        assert_eq!((3, 6), (error.line, error.column));
+
+        let error = parse_condition("less!(vy!(),constant!(0.5000)").unwrap_err();
+        assert_eq!((1, 30), (error.line, error.column));
+
+        let error = parse_expression("vy!() vy!()").unwrap_err();
+        assert_eq!((1, 7), (error.line, error.column));
+    }
+
+    #[test]
+    fn parsing_the_source_of_random_programs_should_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let program = rng.gen::<Program>();
+            let source = program.source();
+            assert_eq!(source, parse_program(&source).unwrap().source());
+        }
+    }
 }
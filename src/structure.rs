@@ -154,7 +154,7 @@ impl fmt::Display for Expression {
 ///
 /// let sensor: ast::structure::Sensor = ast::structure::Sensor::Vy;
 /// ```
-#[derive(Debug,RustcDecodable,RustcEncodable,Clone,PartialEq,Copy)]
+#[derive(Debug,RustcDecodable,RustcEncodable,Clone,PartialEq,Eq,Hash,Copy)]
 pub enum Sensor {
       X,
 	  Y,
@@ -163,6 +163,8 @@ pub enum Sensor {
       O,
       W,
       Fuel,
+      Altitude,
+      PadDistance,
 }
 
 impl fmt::Display for Sensor {
@@ -174,7 +176,9 @@ impl fmt::Display for Sensor {
             Sensor::X    => write!(f, "X"),
             Sensor::Vx   => write!(f, "Vx"),
             Sensor::O    => write!(f, "O"),
-            Sensor::W    => write!(f, "W")
+            Sensor::W    => write!(f, "W"),
+            Sensor::Altitude    => write!(f, "Altitude"),
+            Sensor::PadDistance => write!(f, "PadDistance")
         }
     }
 }
@@ -190,7 +194,7 @@ impl fmt::Display for Sensor {
 ///
 /// let command: ast::structure::Command = ast::structure::Command::Skip;
 /// ```
-#[derive(Debug,RustcDecodable,RustcEncodable,Clone,PartialEq,Copy)]
+#[derive(Debug,RustcDecodable,RustcEncodable,Clone,PartialEq,Eq,Hash,Copy)]
 pub enum Command {
 	Skip,
 	Left,
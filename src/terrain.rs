@@ -0,0 +1,180 @@
+//! The ground that a lander can touch down on
+//!
+//! A `Terrain` consists of a height profile and a number of landing pads. The profile is a line
+//! through a list of points, ordered by x, and stays level beyond the first and the last point.
+//! Pads are flat stretches of ground at their own height, which take the place of the profile
+//! between their left and right edges. Touching down is only a landing when it happens on a pad.
+//!
+//! A `Terrain` has room for `MAX_CORNERS` corners and `MAX_PADS` pads, which keeps it (and
+//! `ast::simulation::World`) `Copy`.
+
+use super::structure::Number;
+
+/// A flat stretch of ground to land on
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Pad {
+    pub left: Number,
+    pub right: Number,
+    pub height: Number
+}
+
+impl Pad {
+    pub fn new(left: Number, right: Number, height: Number) -> Pad {
+        Pad { left: left, right: right, height: height }
+    }
+
+    pub fn contains(&self, x: Number) -> bool {
+        self.left <= x && x <= self.right
+    }
+
+    /// Horizontal distance from x to the nearest point of the pad, positive if the pad lies to
+    /// the right
+    pub fn distance(&self, x: Number) -> Number {
+        if x < self.left {
+            self.left - x
+        } else if x > self.right {
+            self.right - x
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The most corners a profile can have
+pub const MAX_CORNERS: usize = 16;
+/// The most pads a terrain can have
+pub const MAX_PADS: usize = 8;
+
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Terrain {
+    /// Corners of the ground line, ordered by x, of which the first `corner_count` are used
+    corners: [(Number, Number); MAX_CORNERS],
+    corner_count: usize,
+    /// The first `pad_count` are used
+    pads: [Pad; MAX_PADS],
+    pad_count: usize
+}
+
+impl Terrain {
+    /// Flat ground at height 0 without pads. Add pads with `with_pad`, or use `everywhere`.
+    pub fn new() -> Terrain {
+        Terrain {
+            corners: [(0.0, 0.0); MAX_CORNERS],
+            corner_count: 0,
+            pads: [Pad::new(0.0, 0.0, 0.0); MAX_PADS],
+            pad_count: 0
+        }
+    }
+
+    /// Flat ground at height 0 that can be landed on anywhere
+    pub fn everywhere() -> Terrain {
+        Terrain::new().with_pad(Pad::new(::std::f32::NEG_INFINITY, ::std::f32::INFINITY, 0.0))
+    }
+
+    /// Use a profile through the given points, which are sorted by x
+    ///
+    /// Panics if there are more than `MAX_CORNERS` points.
+    pub fn with_profile(self, profile: Vec<(Number, Number)>) -> Terrain {
+        assert!(profile.len() <= MAX_CORNERS, "a profile has at most {} corners, not {}", MAX_CORNERS, profile.len());
+        let mut profile = profile;
+        profile.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+        let mut corners = [(0.0, 0.0); MAX_CORNERS];
+        corners[..profile.len()].copy_from_slice(&profile);
+        Terrain { corners: corners, corner_count: profile.len(), ..self }
+    }
+
+    /// Add a pad
+    ///
+    /// Panics if the terrain already has `MAX_PADS` pads.
+    pub fn with_pad(self, pad: Pad) -> Terrain {
+        assert!(self.pad_count < MAX_PADS, "a terrain has at most {} pads", MAX_PADS);
+        let mut pads = self.pads;
+        pads[self.pad_count] = pad;
+        Terrain { pads: pads, pad_count: self.pad_count + 1, ..self }
+    }
+
+    /// Corners of the ground line, ordered by x
+    pub fn profile(&self) -> &[(Number, Number)] {
+        &self.corners[..self.corner_count]
+    }
+
+    pub fn pads(&self) -> &[Pad] {
+        &self.pads[..self.pad_count]
+    }
+
+    /// The pad at x, if any
+    pub fn pad_at(&self, x: Number) -> Option<&Pad> {
+        self.pads().iter().find(|pad| pad.contains(x))
+    }
+
+    /// Height of the ground at x
+    pub fn ground_height(&self, x: Number) -> Number {
+        if let Some(pad) = self.pad_at(x) {
+            return pad.height;
+        }
+
+        let profile = self.profile();
+        match profile.iter().position(|&(px, _)| px > x) {
+            None if profile.is_empty() => 0.0,
+            None                       => profile[profile.len() - 1].1,
+            Some(0)                    => profile[0].1,
+            Some(i) => {
+                let (x0, y0) = profile[i - 1];
+                let (x1, y1) = profile[i];
+                y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+            }
+        }
+    }
+
+    /// Height above the ground beneath the point (x, y)
+    pub fn altitude(&self, x: Number, y: Number) -> Number {
+        y - self.ground_height(x)
+    }
+
+    /// Horizontal distance from x to the nearest point of the nearest pad, positive if the pad
+    /// lies to the right, 0 above a pad or if there are no pads
+    pub fn pad_distance(&self, x: Number) -> Number {
+        self.pads().iter()
+            .map(|pad| pad.distance(x))
+            .fold(None, |nearest: Option<Number>, distance| match nearest {
+                Some(n) if n.abs() <= distance.abs() => Some(n),
+                _ => Some(distance)
+            })
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valley() -> Terrain {
+        Terrain::new()
+            .with_profile(vec![(-10.0, 20.0), (0.0, 0.0), (10.0, 10.0)])
+            .with_pad(Pad::new(-2.0, 2.0, 1.0))
+            .with_pad(Pad::new(20.0, 30.0, 10.0))
+    }
+
+    #[test]
+    fn ground_follows_the_profile_and_the_pads() {
+        let terrain = valley();
+
+        assert_eq!(20.0, terrain.ground_height(-50.0));
+        assert_eq!(10.0, terrain.ground_height(-5.0));
+        assert_eq!(1.0, terrain.ground_height(1.0));
+        assert_eq!(5.0, terrain.ground_height(5.0));
+        assert_eq!(10.0, terrain.ground_height(50.0));
+        assert_eq!(0.0, Terrain::new().ground_height(3.0));
+    }
+
+    #[test]
+    fn pad_distance_points_to_the_nearest_pad() {
+        let terrain = valley();
+
+        assert_eq!(0.0, terrain.pad_distance(0.5));
+        assert_eq!(-3.0, terrain.pad_distance(5.0));
+        assert_eq!(5.0, terrain.pad_distance(15.0));
+        assert_eq!(-5.0, terrain.pad_distance(35.0));
+        assert_eq!(0.0, Terrain::everywhere().pad_distance(1000.0));
+    }
+}
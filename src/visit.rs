@@ -96,6 +96,18 @@ impl <'a> BucketCollector<'a> {
         }
     }
 
+    /// The node that the collection started from, if it was a whole tree
+    ///
+    /// Nodes are collected in pre-order, and a tree never holds a node of a type that is higher
+    /// up in the grammar than its root, so the root is the first node of the first filled bucket.
+    pub fn root(&self) -> Option<NodeRef<'a>> {
+        self.programs.first().map(|&p| NodeRef::Program(p))
+            .or_else(|| self.conditions.first().map(|&c| NodeRef::Condition(c)))
+            .or_else(|| self.expressions.first().map(|&e| NodeRef::Expression(e)))
+            .or_else(|| self.commands.first().map(|&c| NodeRef::Command(c)))
+            .or_else(|| self.sensors.first().map(|&s| NodeRef::Sensor(s)))
+    }
+
     pub fn get_counts(&self) -> Vec<(NodeType, usize)> {
         vec![(NodeType::Program,   self.programs.len()),
              (NodeType::Expression, self.expressions.len()),
@@ -127,6 +139,16 @@ impl <'a> Visitor<'a> for BucketCollector<'a> {
     }
 }
 
+/// A reference to a node of any type
+#[derive(Copy, Clone, Debug)]
+pub enum NodeRef<'a> {
+    Program(&'a Program),
+    Condition(&'a Condition),
+    Command(&'a Command),
+    Expression(&'a Expression),
+    Sensor(&'a Sensor)
+}
+
 #[derive(Copy, Clone)]
 pub enum NodeType {
     Program,